@@ -1,37 +1,183 @@
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
-use cosmwasm_std::{CosmosMsg, BankMsg, WasmMsg, coins, from_binary, to_binary, Binary, Deps, DepsMut, Env, MessageInfo, Response, StdResult, Addr, StdError, Order, Uint64, Uint128, Coin};
-use cw2::set_contract_version;
-use cw20::{Cw20ReceiveMsg, Cw20ExecuteMsg};
+use cosmwasm_std::{CosmosMsg, BankMsg, from_binary, to_binary, Binary, Decimal, Deps, DepsMut, Env, Event, MessageInfo, Reply, ReplyOn, Response, StdResult, Addr, StdError, Fraction, Order, Storage, SubMsg, SubMsgExecutionResponse, Uint64, Uint128, WasmMsg};
+use cw2::{get_contract_version, set_contract_version};
+use cw20::{AllowanceResponse, BalanceResponse, Cw20QueryMsg, Cw20ReceiveMsg, Expiration};
+use cw_storage_plus::{Bound, U64Key};
+use cw721_base::{ExecuteMsg as Cw721ExecuteMsg, InstantiateMsg as Cw721InstantiateMsg, MintMsg};
+use protobuf::Message;
+use semver::Version;
+use sha2::{Digest, Sha256};
 use asset::{Asset, AssetInfo};
 
 use crate::error::ContractError;
-use crate::msg::{ExecuteMsg, InstantiateMsg, QueryMsg, PaymentRequestResponse, Cw20HookMsg};
-use crate::state::{PaymentRequest, STATE, State, PAYMENT_REQUESTS};
+use crate::helpers::{add_asset, amount_of, assets_to_coins, coins_to_assets, format_assets, resolve_milestones, split_fee, transfer_from_msg, transfer_msgs, validate_basket, zero_assets, MAX_BPS};
+use crate::msg::{ExecuteMsg, InstantiateMsg, Invoice, InvoicePayload, MigrateMsg, NewPaymentRequest, PriceFeedQueryMsg, PriceResponse, QueryMsg, ReceiptMetadata, BalancesResponse, ConfigResponse, MerchantFeeResponse, MerchantResponse, MerchantsResponse, PaymentRequestResponse, PaymentRequestsResponse, ContributionsResponse, MilestonesResponse, DisputeResponse, DisputesResponse, Cw20HookMsg, OfferResponse, OffersResponse, PaymentIntentResponse, PaymentIntentsResponse, PlanResponse, StatsResponse, AssetStatsResponse, SubscriptionResponse, SubscriptionsResponse};
+use crate::response::MsgInstantiateContractResponse;
+use crate::state::{LegacyPaymentRequest, LEGACY_PAYMENT_REQUESTS, LEGACY_STATE, AssetStats, Stats, ASSET_STATS, MERCHANT_STATS, TOTAL_STATS, Contribution, Dispute, DisputeResolution, Merchant, MerchantStatus, MERCHANTS, MilestoneShare, MilestoneStatus, PaymentRequest, PaymentRequestStatus, CONTRIBUTIONS, DISPUTES, MERCHANT_FEES, MERCHANT_PUBKEYS, PULL_PAYMENTS, BALANCES, USED_NONCES, STATE, State, payment_requests, request_key, Plan, Subscription, SubscriptionStatus, PLANS, plan_key, subscription_key, LAST_PLAN_ID, Offer, OFFERS, offer_key, LAST_OFFER_ID, PaymentIntent, payment_intents, intent_key, LAST_INTENT_ID, LAST_SUBSCRIPTION_ID, subscriptions};
 
 
 // version info for migration info
-const CONTRACT_NAME: &str = "crates.io:sps";
+const CONTRACT_NAME: &str = "crates.io:escrow";
+// name the first release was stored under
+const LEGACY_CONTRACT_NAME: &str = "crates.io:sps";
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
+const INSTANTIATE_REPLY_ID: u64 = 1;
+
+// settings for pagination
+const MAX_LIMIT: u32 = 30;
+const DEFAULT_LIMIT: u32 = 10;
+
+// consecutive missed periods after which a subscription lapses
+const MAX_MISSED_PAYMENTS: u32 = 3;
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
     deps: DepsMut,
-    _env: Env,
-    _info: MessageInfo,
+    env: Env,
+    info: MessageInfo,
     msg: InstantiateMsg,
 ) -> Result<Response, ContractError> {
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    validate_fee(msg.fee_bps)?;
+    let arbiter = match msg.arbiter {
+        None => None,
+        Some(arbiter) => Some(deps.api.addr_validate(arbiter.as_str())?),
+    };
     let state = State {
-        // Todo: validate the shop contract
-        shop: msg.shop.clone(),
-        last_id: Uint64::from(0 as u64),
+        shop: deps.api.addr_validate(msg.shop.as_str())?,
+        arbiter,
+        admin: info.sender,
+        fee_bps: msg.fee_bps,
+        confirmation_window: msg.confirmation_window,
+        receipt_contract: None,
+        price_feed: None,
+        price_assets: vec![],
+        max_slippage_bps: 0,
+        last_id: Uint64::zero(),
     };
-    match STATE.save(deps.storage, &state) {
-        Ok(_) => Ok(Response::new()
-                    .add_attribute("method", "instantiate")),
-        Err(e) => Err(ContractError::Std(e)),
+    STATE.save(deps.storage, &state)?;
+
+    let mut response = Response::new().add_attribute("method", "instantiate");
+    if let Some(code_id) = msg.receipt_code_id {
+        response = response.add_submessage(SubMsg {
+            // Create the receipt collection
+            msg: WasmMsg::Instantiate {
+                admin: None,
+                code_id,
+                msg: to_binary(&Cw721InstantiateMsg {
+                    name: "escrow receipts".to_string(),
+                    symbol: "RCPT".to_string(),
+                    minter: env.contract.address.to_string(),
+                })?,
+                funds: vec![],
+                label: format!("{} receipts", CONTRACT_NAME),
+            }
+            .into(),
+            gas_limit: None,
+            id: INSTANTIATE_REPLY_ID,
+            reply_on: ReplyOn::Success,
+        });
+    }
+    Ok(response)
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(deps: DepsMut, _env: Env, msg: Reply) -> StdResult<Response> {
+    if msg.id != INSTANTIATE_REPLY_ID {
+        return Err(StdError::generic_err(format!("Unknown reply id: {}", msg.id)));
+    }
+    let result: SubMsgExecutionResponse =
+        msg.result.into_result().map_err(StdError::generic_err)?;
+    let data = result
+        .data
+        .ok_or_else(|| StdError::generic_err("Invalid data"))?;
+
+    let res: MsgInstantiateContractResponse =
+        Message::parse_from_bytes(data.as_slice()).map_err(|_| {
+            StdError::parse_err("MsgInstantiateContractResponse", "failed to parse data")
+        })?;
+    let receipt_contract = res.contract_address;
+    let api = deps.api;
+    STATE.update(deps.storage, |mut state| -> StdResult<_> {
+        state.receipt_contract = Some(api.addr_validate(receipt_contract.as_str())?);
+        Ok(state)
+    })?;
+    Ok(Response::new().add_attribute("receipt_contract", receipt_contract))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, _env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
+    let stored = get_contract_version(deps.storage)?;
+    let invalid = || ContractError::InvalidMigration { contract: stored.contract.clone(), version: stored.version.clone() };
+    let stored_version = Version::parse(&stored.version).map_err(|_| invalid())?;
+    let version = Version::parse(CONTRACT_VERSION).map_err(|e| StdError::generic_err(e.to_string()))?;
+    if stored_version > version {
+        return Err(invalid());
+    }
+    match stored.contract.as_str() {
+        LEGACY_CONTRACT_NAME => migrate_legacy_storage(deps.storage)?,
+        CONTRACT_NAME => {},
+        _ => return Err(invalid()),
     }
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    Ok(Response::new().add_attributes(vec![("method", "migrate"), ("from_version", stored.version.as_str())]))
+}
+
+/// Rewrites the state and the open payment requests of the first release. The shop
+/// becomes the admin. Requests paid before the upgrade get no `paid_at`, so they are
+/// never released without the customer's confirmation.
+fn migrate_legacy_storage(storage: &mut dyn Storage) -> StdResult<()> {
+    let legacy_state = LEGACY_STATE.load(storage)?;
+    STATE.save(storage, &State {
+        last_id: legacy_state.last_id,
+        admin: legacy_state.shop.clone(),
+        shop: legacy_state.shop,
+        arbiter: None,
+        fee_bps: 0,
+        confirmation_window: 0,
+        receipt_contract: None,
+        price_feed: None,
+        price_assets: vec![],
+        max_slippage_bps: 0,
+    })?;
+
+    let legacy: Vec<LegacyPaymentRequest> = LEGACY_PAYMENT_REQUESTS
+        .range(storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, p)| p))
+        .collect::<StdResult<_>>()?;
+    for old in legacy {
+        let paid = Asset { info: old.asset.info.clone(), amount: old.paid_amount };
+        let status = if old.paid_amount.is_zero() {
+            PaymentRequestStatus::Unpaid
+        } else if old.paid_amount >= old.asset.amount {
+            PaymentRequestStatus::Paid
+        } else {
+            PaymentRequestStatus::PartiallyPaid
+        };
+        if !old.paid_amount.is_zero() {
+            CONTRIBUTIONS.save(storage, (old.id.as_str(), &old.customer), &vec![paid.clone()])?;
+        }
+        let p = PaymentRequest {
+            paid_at: None,
+            id: old.id,
+            assets: vec![old.asset],
+            order_id: old.order_id,
+            paid_assets: vec![paid],
+            refund_amount: old.refund_amount,
+            merchant: old.merchant,
+            customer: old.customer,
+            status,
+            expires: None,
+            hashlock: None,
+            milestones: vec![],
+            reference_amount: None,
+        };
+        // Moves the record from its legacy string key and builds the indexes
+        LEGACY_PAYMENT_REQUESTS.remove(storage, p.id.clone());
+        payment_requests().save(storage, request_key(&p.id)?, &p)?;
+    }
+    Ok(())
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
@@ -42,161 +188,1479 @@ pub fn execute(
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
     match msg {
-        ExecuteMsg::CreatePaymentRequest { asset, order_id } => create_payment_request(deps, info, asset, order_id),
-        ExecuteMsg::PayIntoPaymentRequest { id } => pay_into_payment_request(deps, info, id),
-        ExecuteMsg::ApproveRefund { asset, id } => Ok(Response::default()),
-        ExecuteMsg::SettlePaymentRequest { id } => settle_payment_request(deps, info, id),
+        ExecuteMsg::ApplyAsMerchant { name, payout_address, accepted_assets } => apply_as_merchant(deps, info, name, payout_address, accepted_assets),
+        ExecuteMsg::ApproveMerchant { merchant } => set_merchant_status(deps, info, merchant, MerchantStatus::Approved),
+        ExecuteMsg::SuspendMerchant { merchant } => set_merchant_status(deps, info, merchant, MerchantStatus::Suspended),
+        ExecuteMsg::CreatePaymentRequest { assets, order_id, expires } => create_payment_request(deps, info, assets, order_id, expires),
+        ExecuteMsg::CreatePaymentRequests { requests } => create_payment_requests(deps, info, requests),
+        ExecuteMsg::CreatePricedPaymentRequest { amount, order_id, expires } => create_priced_payment_request(deps, info, amount, order_id, expires),
+        ExecuteMsg::CreateHtlc { assets, order_id, hashlock, expires } => create_htlc(deps, info, assets, order_id, hashlock, expires),
+        ExecuteMsg::CancelPaymentRequest { id } => cancel_payment_request(deps, info, id),
+        ExecuteMsg::SetMilestones { id, milestones } => set_milestones(deps, info, id, milestones),
+        ExecuteMsg::ApproveMilestone { id, milestone } => approve_milestone(deps, info, id, milestone),
+        ExecuteMsg::UpdatePaymentRequest { id, assets, order_id } => update_payment_request(deps, info, id, assets, order_id),
+        ExecuteMsg::RegisterPubkey { pubkey } => register_pubkey(deps, info, pubkey),
+        ExecuteMsg::PayInvoice { invoice, signature } => pay_invoice(deps, env, info, invoice, signature),
+        ExecuteMsg::PayIntoPaymentRequest { id } => pay_into_payment_request(deps, env, info, id),
+        ExecuteMsg::ApproveRefund { .. } => Ok(Response::default()),
+        ExecuteMsg::SettlePaymentRequest { id } => settle_payment_request(deps, env, info, id),
+        ExecuteMsg::SettlePaymentRequests { ids } => settle_payment_requests(deps, env, info, ids),
+        ExecuteMsg::Release { id } => release(deps, env, id),
+        ExecuteMsg::ClaimHtlc { id, preimage } => claim_htlc(deps, env, id, preimage),
+        ExecuteMsg::Reclaim { id } => reclaim(deps, env, info, id),
+        ExecuteMsg::RaiseDispute { id, reason } => raise_dispute(deps, env, info, id, reason),
+        ExecuteMsg::ResolveDispute { id, merchant_assets } => resolve_dispute(deps, info, id, merchant_assets),
+        ExecuteMsg::CreateOffer { ask, counterparty, partial_fills } => {
+            if info.funds.len() != 1 {
+                return Err(ContractError::InvalidOffer {});
+            }
+            let offer = coins_to_assets(&info.funds).remove(0);
+            create_offer(deps, info.sender, offer, ask, counterparty, partial_fills)
+        },
+        ExecuteMsg::FillOffer { id } => {
+            if info.funds.len() != 1 {
+                return Err(ContractError::WrongToken {});
+            }
+            let deposit = coins_to_assets(&info.funds).remove(0);
+            fill_offer(deps, info.sender, id, deposit)
+        },
+        ExecuteMsg::CancelOffer { id } => cancel_offer(deps, info, id),
+        ExecuteMsg::CreatePaymentIntent { merchant, description, expires } => {
+            let assets = coins_to_assets(&info.funds);
+            create_payment_intent(deps, env, info.sender, merchant, description, assets, expires)
+        },
+        ExecuteMsg::AcceptPaymentIntent { id } => accept_payment_intent(deps, env, info, id),
+        ExecuteMsg::RejectPaymentIntent { id } => reject_payment_intent(deps, info, id),
+        ExecuteMsg::ReclaimPaymentIntent { id } => reclaim_payment_intent(deps, env, id),
+        ExecuteMsg::CreatePlan { asset, period } => create_plan(deps, info, asset, period),
+        ExecuteMsg::Subscribe { plan_id } => subscribe(deps, env, info, plan_id),
+        ExecuteMsg::Collect { subscription_id } => collect(deps, env, subscription_id),
+        ExecuteMsg::CancelSubscription { subscription_id } => cancel_subscription(deps, info, subscription_id),
+        ExecuteMsg::UpdateConfig { admin, fee_bps, confirmation_window } => update_config(deps, info, admin, fee_bps, confirmation_window),
+        ExecuteMsg::SetMerchantFee { merchant, fee_bps } => set_merchant_fee(deps, info, merchant, fee_bps),
+        ExecuteMsg::SetPriceFeed { price_feed, assets, max_slippage_bps } => set_price_feed(deps, info, price_feed, assets, max_slippage_bps),
+        ExecuteMsg::SetPullPayments { enabled } => set_pull_payments(deps, info, enabled),
+        ExecuteMsg::Withdraw { assets } => withdraw(deps, info, assets),
         ExecuteMsg::Receive(msg) => receive_cw20(deps, env, info, msg),
     }
 }
 
-pub fn receive_cw20(deps: DepsMut, _: Env, info: MessageInfo, msg: Cw20ReceiveMsg) -> Result<Response, ContractError> {
-    let contract_addr = info.sender.clone();
+pub fn update_config(deps: DepsMut, info: MessageInfo, admin: Option<String>, fee_bps: Option<u64>, confirmation_window: Option<u64>) -> Result<Response, ContractError> {
+    let mut state = STATE.load(deps.storage)?;
+    if info.sender != state.admin {
+        return Err(ContractError::Unauthorized {});
+    }
+    if let Some(admin) = admin {
+        state.admin = deps.api.addr_validate(&admin)?;
+    }
+    if let Some(fee_bps) = fee_bps {
+        validate_fee(fee_bps)?;
+        state.fee_bps = fee_bps;
+    }
+    if let Some(confirmation_window) = confirmation_window {
+        state.confirmation_window = confirmation_window;
+    }
+    STATE.save(deps.storage, &state)?;
+    Ok(Response::new().add_attribute("method", "update_config"))
+}
+
+/// Overrides the platform fee for a single merchant, or removes the override when
+/// `fee_bps` is `None`
+pub fn set_merchant_fee(deps: DepsMut, info: MessageInfo, merchant: String, fee_bps: Option<u64>) -> Result<Response, ContractError> {
+    let state = STATE.load(deps.storage)?;
+    if info.sender != state.admin {
+        return Err(ContractError::Unauthorized {});
+    }
+    let merchant = deps.api.addr_validate(&merchant)?;
+    match fee_bps {
+        None => MERCHANT_FEES.remove(deps.storage, &merchant),
+        Some(fee_bps) => {
+            validate_fee(fee_bps)?;
+            MERCHANT_FEES.save(deps.storage, &merchant, &fee_bps)?;
+        }
+    }
+    Ok(Response::new().add_attributes(vec![("method", "set_merchant_fee"), ("merchant", merchant.as_str())]))
+}
+
+/// Configures the oracle priced requests are quoted with and the assets they can be paid
+/// in. Unsetting the feed leaves existing priced requests unpayable.
+pub fn set_price_feed(deps: DepsMut, info: MessageInfo, price_feed: Option<String>, assets: Vec<AssetInfo>, max_slippage_bps: u64) -> Result<Response, ContractError> {
+    let mut state = STATE.load(deps.storage)?;
+    if info.sender != state.admin {
+        return Err(ContractError::Unauthorized {});
+    }
+    if max_slippage_bps > MAX_BPS {
+        return Err(ContractError::InvalidSlippage { slippage_bps: max_slippage_bps });
+    }
+    state.price_feed = price_feed.map(|addr| deps.api.addr_validate(&addr)).transpose()?;
+    state.price_assets = assets;
+    state.max_slippage_bps = max_slippage_bps;
+    STATE.save(deps.storage, &state)?;
+    Ok(Response::new().add_attribute("method", "set_price_feed"))
+}
+
+/// Registers the sender as a merchant pending the shop's approval. Applying again updates
+/// the profile and keeps the current status.
+pub fn apply_as_merchant(deps: DepsMut, info: MessageInfo, name: String, payout_address: Option<String>, accepted_assets: Vec<AssetInfo>) -> Result<Response, ContractError> {
+    let payout_address = match payout_address {
+        Some(payout_address) => deps.api.addr_validate(&payout_address)?,
+        None => info.sender.clone(),
+    };
+    let status = match MERCHANTS.may_load(deps.storage, &info.sender)? {
+        Some(merchant) => merchant.status,
+        None => MerchantStatus::Pending,
+    };
+    let merchant = Merchant {
+        address: info.sender.clone(),
+        name,
+        payout_address,
+        accepted_assets,
+        status,
+    };
+    MERCHANTS.save(deps.storage, &info.sender, &merchant)?;
+    Ok(Response::new().add_attributes(vec![("method", "apply_as_merchant"), ("merchant", info.sender.as_str())]))
+}
+
+pub fn set_merchant_status(deps: DepsMut, info: MessageInfo, merchant: String, status: MerchantStatus) -> Result<Response, ContractError> {
+    let state = STATE.load(deps.storage)?;
+    if info.sender != state.shop {
+        return Err(ContractError::Unauthorized {});
+    }
+    let address = deps.api.addr_validate(&merchant)?;
+    let mut merchant = match MERCHANTS.may_load(deps.storage, &address)? {
+        None => return Err(ContractError::Std(StdError::NotFound {kind: String::from("merchant")})),
+        Some(merchant) => merchant,
+    };
+    let method = match status {
+        MerchantStatus::Approved => "approve_merchant",
+        _ => "suspend_merchant",
+    };
+    merchant.status = status;
+    MERCHANTS.save(deps.storage, &address, &merchant)?;
+    Ok(Response::new().add_attributes(vec![("method", method), ("merchant", address.as_str())]))
+}
+
+/// Where the merchant's funds are sent: the registered payout address, or the merchant
+/// itself when it has no profile
+fn payout_address(storage: &dyn Storage, merchant: &Addr) -> StdResult<Addr> {
+    Ok(MERCHANTS.may_load(storage, merchant)?
+        .map(|m| m.payout_address)
+        .unwrap_or_else(|| merchant.clone()))
+}
+
+pub fn set_pull_payments(deps: DepsMut, info: MessageInfo, enabled: bool) -> Result<Response, ContractError> {
+    if enabled {
+        PULL_PAYMENTS.save(deps.storage, &info.sender, &true)?;
+    } else {
+        PULL_PAYMENTS.remove(deps.storage, &info.sender);
+    }
+    Ok(Response::new().add_attributes(vec![("method", "set_pull_payments"), ("enabled", enabled.to_string().as_str())]))
+}
+
+/// Pays out the sender's credited balances in one transaction: the given amounts, or
+/// everything when `assets` is `None`
+pub fn withdraw(deps: DepsMut, info: MessageInfo, assets: Option<Vec<Asset>>) -> Result<Response, ContractError> {
+    let assets = match assets {
+        Some(assets) => assets,
+        None => BALANCES
+            .prefix(&info.sender)
+            .range(deps.storage, None, None, Order::Ascending)
+            .map(|item| item.map(|(_, balance)| balance))
+            .collect::<StdResult<Vec<Asset>>>()?,
+    };
+    for asset in assets.iter() {
+        let denom = asset.info.to_string();
+        let key = (&info.sender, denom.as_str());
+        let mut balance = match BALANCES.may_load(deps.storage, key)? {
+            Some(balance) if balance.amount >= asset.amount => balance,
+            _ => return Err(ContractError::InsufficientBalance {}),
+        };
+        balance.amount = balance.amount.checked_sub(asset.amount).map_err(StdError::from)?;
+        if balance.amount.is_zero() {
+            BALANCES.remove(deps.storage, key);
+        } else {
+            BALANCES.save(deps.storage, key, &balance)?;
+        }
+    }
+
+    Ok(Response::new()
+        .add_messages(transfer_msgs(&assets, &payout_address(deps.storage, &info.sender)?)?)
+        .add_attributes(vec![("method", "withdraw"), ("withdrawn", format_assets(&assets).as_str())]))
+}
+
+fn validate_fee(fee_bps: u64) -> Result<(), ContractError> {
+    if fee_bps > MAX_BPS {
+        return Err(ContractError::InvalidFee { fee_bps });
+    }
+    Ok(())
+}
+
+fn merchant_fee_bps(storage: &dyn Storage, state: &State, merchant: &Addr) -> StdResult<u64> {
+    Ok(MERCHANT_FEES.may_load(storage, merchant)?.unwrap_or(state.fee_bps))
+}
+
+/// Releases `assets` to the merchant's payout address after taking the platform fee for
+/// the shop. Merchants on pull payments are credited instead and withdraw later.
+fn pay_merchant(storage: &mut dyn Storage, merchant: &Addr, assets: &[Asset]) -> StdResult<Response> {
+    let state = STATE.load(storage)?;
+    let fee_bps = merchant_fee_bps(storage, &state, merchant)?;
+    let (net, fees) = split_fee(assets, fee_bps)?;
+    update_stats(storage, merchant, |s| add_volume(&mut s.settled_volume, assets))?;
+    update_asset_stats(storage, assets, |s, amount| s.settled += amount)?;
+
+    let mut response = Response::new();
+    if PULL_PAYMENTS.may_load(storage, merchant)?.unwrap_or(false) {
+        for asset in net.iter().filter(|a| !a.amount.is_zero()) {
+            BALANCES.update(storage, (merchant, asset.info.to_string().as_str()), |b| -> StdResult<_> {
+                match b {
+                    None => Ok(asset.clone()),
+                    Some(mut balance) => {
+                        balance.amount += asset.amount;
+                        Ok(balance)
+                    }
+                }
+            })?;
+        }
+        response = response.add_attribute("credited", format_assets(&net));
+    } else {
+        response = response.add_messages(transfer_msgs(&net, &payout_address(storage, merchant)?)?);
+    }
+
+    Ok(response
+        .add_messages(transfer_msgs(&fees, &state.shop)?)
+        .add_attribute("fee_bps", fee_bps.to_string())
+        .add_attribute("fee", format_assets(&fees)))
+}
+
+pub fn receive_cw20(mut deps: DepsMut, env: Env, info: MessageInfo, msg: Cw20ReceiveMsg) -> Result<Response, ContractError> {
+    let contract_addr = info.sender;
     match from_binary::<Cw20HookMsg>(&msg.msg) {
         Ok(Cw20HookMsg::PayIntoPaymentRequest {id}) => {
-            let customer = deps.api.addr_validate(&msg.sender).unwrap(); // TODO: how to properly handle this?
-            match PAYMENT_REQUESTS.may_load(deps.storage, id.clone())? {
+            let customer = deps.api.addr_validate(&msg.sender)?;
+            match payment_requests().may_load(deps.storage, request_key(&id)?)? {
                 None => Err(ContractError::Std(StdError::NotFound {kind: String::from("payment_request")})),
-                Some(p) => { 
-                    match p.asset.info {
-                        AssetInfo::NativeToken { denom: _ } => Err(ContractError::WrongToken {}),
-                        AssetInfo::Token { contract_addr: pr_contract_addr } => {
-                            if contract_addr != pr_contract_addr {
-                                return Err(ContractError::WrongToken {});
-                            }
-                            if p.asset.amount != msg.amount {
-                                return Err(ContractError::WrongAmount {amount: msg.amount});
-                            }
-                            PAYMENT_REQUESTS.update(deps.storage, id, |p_new_| -> StdResult<_> {
-                                match p_new_ {
-                                    None => Err(StdError::NotFound {kind: String::from("payment_request")}),
-                                    Some(mut p_new) => {
-                                        p_new.paid_amount = msg.amount;
-                                        p_new.customer = customer.clone();
-                                        Ok(p_new)
-                                    }
-                                }
-                            })?;
-                            Ok(Response::default())
-                        }
+                Some(mut p) => {
+                    if is_expired(&p, &env) {
+                        return Err(ContractError::Expired {});
                     }
+                    let offered = vec![Asset {
+                        info: AssetInfo::Token { contract_addr: contract_addr.to_string() },
+                        amount: msg.amount,
+                    }];
+                    quote_priced_request(deps.as_ref(), &mut p, &offered)?;
+                    let (excess, event) = record_payment(deps.storage, &env, p, &customer, offered)?;
+
+                    // Return whatever was sent above the outstanding amount
+                    Ok(Response::new()
+                        .add_messages(transfer_msgs(&excess, &customer)?)
+                        .add_event(event))
                 }
             }
         },
+        Ok(Cw20HookMsg::PayInvoice {invoice, signature}) => {
+            let customer = deps.api.addr_validate(&msg.sender)?;
+            let merchant = verify_invoice(deps.branch(), &env, &invoice, &signature)?;
+            let p = save_new_payment_request(deps.storage, merchant, invoice.assets, invoice.order_id, Some(invoice.expires), None, None)?;
+            let id = p.id.clone();
+            let created = escrow_event("create", &p, &p.assets);
+            let offered = Asset {
+                info: AssetInfo::Token { contract_addr: contract_addr.to_string() },
+                amount: msg.amount,
+            };
+            let (excess, paid) = record_payment(deps.storage, &env, p, &customer, vec![offered])?;
+
+            Ok(Response::new()
+                .add_messages(transfer_msgs(&excess, &customer)?)
+                .add_events(vec![created, paid])
+                .add_attributes(vec![("method", "pay_invoice"), ("id", id.as_str())]))
+        },
+        Ok(Cw20HookMsg::CreateOffer {ask, counterparty, partial_fills}) => {
+            let maker = deps.api.addr_validate(&msg.sender)?;
+            let offer = Asset {
+                info: AssetInfo::Token { contract_addr: contract_addr.to_string() },
+                amount: msg.amount,
+            };
+            create_offer(deps, maker, offer, ask, counterparty, partial_fills)
+        },
+        Ok(Cw20HookMsg::CreatePaymentIntent {merchant, description, expires}) => {
+            let customer = deps.api.addr_validate(&msg.sender)?;
+            let assets = vec![Asset {
+                info: AssetInfo::Token { contract_addr: contract_addr.to_string() },
+                amount: msg.amount,
+            }];
+            create_payment_intent(deps, env, customer, merchant, description, assets, expires)
+        },
+        Ok(Cw20HookMsg::FillOffer {id}) => {
+            let taker = deps.api.addr_validate(&msg.sender)?;
+            let deposit = Asset {
+                info: AssetInfo::Token { contract_addr: contract_addr.to_string() },
+                amount: msg.amount,
+            };
+            fill_offer(deps, taker, id, deposit)
+        },
         _ => Err(ContractError::Std(StdError::GenericErr {msg: String::from("unknown hook")}))
     }
 }
 
-pub fn create_payment_request(deps: DepsMut, info: MessageInfo, asset: Asset, order_id: String) -> Result<Response, ContractError> {
-    let merchant = info.sender;
-    let state_ = STATE.may_load(deps.storage)?;
+pub fn create_payment_request(deps: DepsMut, info: MessageInfo, assets: Vec<Asset>, order_id: String, expires: Option<Expiration>) -> Result<Response, ContractError> {
+    let payment_request = save_new_payment_request(deps.storage, info.sender, assets, order_id, expires, None, None)?;
+    Ok(Response::new()
+        .add_attribute("id", payment_request.id.as_str())
+        .add_event(escrow_event("create", &payment_request, &payment_request.assets)))
+}
+
+/// Creates a request for `amount` in the price feed's reference unit. It can be paid in
+/// any whitelisted asset, at the oracle price at the time of payment.
+pub fn create_priced_payment_request(deps: DepsMut, info: MessageInfo, amount: Uint128, order_id: String, expires: Option<Expiration>) -> Result<Response, ContractError> {
+    let payment_request = save_new_payment_request(deps.storage, info.sender, vec![], order_id, expires, None, Some(amount))?;
+    Ok(Response::new()
+        .add_attributes(vec![("method", "create_priced_payment_request"), ("id", payment_request.id.as_str())])
+        .add_event(escrow_event("create", &payment_request, &[])))
+}
+
+/// Creates every request in `requests` or none of them. A failing item is reported with
+/// its position in the batch.
+pub fn create_payment_requests(deps: DepsMut, info: MessageInfo, requests: Vec<NewPaymentRequest>) -> Result<Response, ContractError> {
+    let mut ids: Vec<String> = vec![];
+    let mut events: Vec<Event> = vec![];
+    for (index, request) in requests.into_iter().enumerate() {
+        let payment_request = save_new_payment_request(deps.storage, info.sender.clone(), request.assets, request.order_id, request.expires, None, None)
+            .map_err(|e| ContractError::BatchItem { index: index as u32, error: e.to_string() })?;
+        events.push(escrow_event("create", &payment_request, &payment_request.assets));
+        ids.push(payment_request.id);
+    }
+    Ok(Response::new()
+        .add_attributes(vec![("method", "create_payment_requests"), ("ids", ids.join(",").as_str())])
+        .add_events(events))
+}
+
+/// Creates a hash time-locked request. Once funded through the usual payment paths,
+/// whoever presents the preimage of `hashlock` before `expires` releases the funds to
+/// the merchant; after that the payers can reclaim them.
+pub fn create_htlc(deps: DepsMut, info: MessageInfo, assets: Vec<Asset>, order_id: String, hashlock: Binary, expires: Expiration) -> Result<Response, ContractError> {
+    if hashlock.len() != 32 {
+        return Err(ContractError::InvalidHashlock {});
+    }
+    // Without a timeout an unclaimed HTLC could never be reclaimed
+    if let Expiration::Never {} = expires {
+        return Err(ContractError::InvalidExpiration {});
+    }
+    let payment_request = save_new_payment_request(deps.storage, info.sender, assets, order_id, Some(expires), Some(hashlock), None)?;
+    Ok(Response::new()
+        .add_attributes(vec![("method", "create_htlc"), ("id", payment_request.id.as_str())])
+        .add_event(escrow_event("create", &payment_request, &payment_request.assets)))
+}
+
+/// Only approved merchants can request payments, and only in the assets they accept
+fn validate_merchant_assets(storage: &dyn Storage, merchant: &Addr, assets: &[Asset]) -> Result<(), ContractError> {
+    match MERCHANTS.may_load(storage, merchant)? {
+        Some(profile) if profile.status == MerchantStatus::Approved => {
+            if !profile.accepted_assets.is_empty() && !assets.iter().all(|a| profile.accepted_assets.contains(&a.info)) {
+                return Err(ContractError::AssetNotAccepted {});
+            }
+            Ok(())
+        },
+        _ => Err(ContractError::MerchantNotApproved {}),
+    }
+}
+
+fn save_new_payment_request(storage: &mut dyn Storage, merchant: Addr, assets: Vec<Asset>, order_id: String, expires: Option<Expiration>, hashlock: Option<Binary>, reference_amount: Option<Uint128>) -> Result<PaymentRequest, ContractError> {
+    match reference_amount {
+        Some(amount) if amount.is_zero() => return Err(ContractError::InvalidBasket {}),
+        Some(_) => {},
+        None => validate_basket(&assets)?,
+    }
+    validate_merchant_assets(storage, &merchant, &assets)?;
+    let state_ = STATE.may_load(storage)?;
     match state_ {
         None => Err(ContractError::Std(StdError::GenericErr {msg: String::from("contract state invalid")})),
-        Some(state) => {
-            let id = state.last_id + Uint64::from(1 as u64);
-            let id_string = id.to_string();
+        Some(mut state) => {
+            let id = state.last_id + Uint64::from(1u64);
             let payment_request = PaymentRequest {
-                 merchant: merchant.clone(),
+                 merchant,
                  customer: Addr::unchecked( "0"),
-                 asset: asset.clone(),
-                 order_id: order_id.clone(),
-                 id: id_string.clone(),
-                 paid_amount: Uint128::from(0 as u64),
-                 refund_amount: Uint128::from(0 as u64),
+                 paid_assets: zero_assets(&assets),
+                 assets,
+                 order_id,
+                 id: id.to_string(),
+                 refund_amount: Uint128::zero(),
+                 status: PaymentRequestStatus::Unpaid,
+                 expires,
+                 hashlock,
+                 milestones: vec![],
+                 paid_at: None,
+                 reference_amount,
             };
-            match PAYMENT_REQUESTS.save(deps.storage, id_string.clone(), &payment_request) {
-                Ok(_) => Ok(Response::new().add_attribute("id", id_string)),
-                Err(err) => Err(ContractError::Std(err)),
+            state.last_id = id;
+            STATE.save(storage, &state)?;
+            payment_requests().save(storage, request_key(&payment_request.id)?, &payment_request)?;
+            update_stats(storage, &payment_request.merchant, |s| s.requests_created += 1)?;
+            Ok(payment_request)
+        }
+    }
+}
+
+pub fn register_pubkey(deps: DepsMut, info: MessageInfo, pubkey: Binary) -> Result<Response, ContractError> {
+    // Compressed or uncompressed SEC1 encoding
+    if pubkey.len() != 33 && pubkey.len() != 65 {
+        return Err(ContractError::InvalidPubkey {});
+    }
+    MERCHANT_PUBKEYS.save(deps.storage, &info.sender, &pubkey)?;
+    Ok(Response::new().add_attributes(vec![("method", "register_pubkey"), ("merchant", info.sender.as_str())]))
+}
+
+/// Checks that `invoice` was signed by its merchant and has not been used or expired,
+/// then records its nonce so it cannot be replayed.
+fn verify_invoice(deps: DepsMut, env: &Env, invoice: &Invoice, signature: &Binary) -> Result<Addr, ContractError> {
+    let merchant = deps.api.addr_validate(&invoice.merchant)?;
+    let pubkey = match MERCHANT_PUBKEYS.may_load(deps.storage, &merchant)? {
+        None => return Err(ContractError::InvalidPubkey {}),
+        Some(pubkey) => pubkey,
+    };
+    let payload = InvoicePayload {
+        chain_id: env.block.chain_id.clone(),
+        contract: env.contract.address.to_string(),
+        invoice: invoice.clone(),
+    };
+    let hash = Sha256::digest(to_binary(&payload)?.as_slice());
+    let verified = deps.api
+        .secp256k1_verify(&hash, signature.as_slice(), pubkey.as_slice())
+        .map_err(|_| ContractError::InvalidSignature {})?;
+    if !verified {
+        return Err(ContractError::InvalidSignature {});
+    }
+    if invoice.expires.is_expired(&env.block) {
+        return Err(ContractError::Expired {});
+    }
+    let nonce_key = (&merchant, U64Key::new(invoice.nonce));
+    if USED_NONCES.has(deps.storage, nonce_key.clone()) {
+        return Err(ContractError::NonceUsed { nonce: invoice.nonce });
+    }
+    USED_NONCES.save(deps.storage, nonce_key, &true)?;
+    Ok(merchant)
+}
+
+/// Creates the payment request described by a merchant-signed invoice and pays the
+/// attached funds into it
+pub fn pay_invoice(mut deps: DepsMut, env: Env, info: MessageInfo, invoice: Invoice, signature: Binary) -> Result<Response, ContractError> {
+    let merchant = verify_invoice(deps.branch(), &env, &invoice, &signature)?;
+    let p = save_new_payment_request(deps.storage, merchant, invoice.assets, invoice.order_id, Some(invoice.expires), None, None)?;
+    let id = p.id.clone();
+    let created = escrow_event("create", &p, &p.assets);
+    let (excess, paid) = record_payment(deps.storage, &env, p, &info.sender, coins_to_assets(&info.funds))?;
+
+    let refunds = assets_to_coins(&excess);
+    let mut response = Response::new()
+        .add_attributes(vec![("method", "pay_invoice"), ("id", id.as_str())])
+        .add_events(vec![created, paid]);
+    if !refunds.is_empty() {
+        response = response.add_message(CosmosMsg::Bank(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: refunds,
+        }));
+    }
+    Ok(response)
+}
+
+/// Loads a request that its merchant is still allowed to change, i.e. one that
+/// nothing has been paid into yet
+fn load_unpaid_for_merchant(storage: &dyn Storage, merchant: &Addr, id: String) -> Result<PaymentRequest, ContractError> {
+    match payment_requests().may_load(storage, request_key(&id)?)? {
+        None => Err(ContractError::Std(StdError::NotFound {kind: String::from("payment_request")})),
+        Some(p) => {
+            if p.merchant != *merchant {
+                return Err(ContractError::Unauthorized {});
+            }
+            match p.status {
+                PaymentRequestStatus::Unpaid => Ok(p),
+                PaymentRequestStatus::Cancelled => Err(ContractError::Cancelled {}),
+                _ => Err(ContractError::AlreadyPaid {}),
             }
         }
     }
 }
 
-pub fn pay_into_payment_request(deps: DepsMut, info: MessageInfo, id: String) -> Result<Response, ContractError> {
+pub fn cancel_payment_request(deps: DepsMut, info: MessageInfo, id: String) -> Result<Response, ContractError> {
+    let mut p = load_unpaid_for_merchant(deps.storage, &info.sender, id.clone())?;
+    p.status = PaymentRequestStatus::Cancelled;
+    payment_requests().save(deps.storage, request_key(&id)?, &p)?;
+    Ok(Response::new()
+        .add_attributes(vec![("method", "cancel_payment_request"), ("id", id.as_str())])
+        .add_event(escrow_event("cancel", &p, &p.assets)))
+}
+
+pub fn update_payment_request(deps: DepsMut, info: MessageInfo, id: String, assets: Option<Vec<Asset>>, order_id: Option<String>) -> Result<Response, ContractError> {
+    let mut p = load_unpaid_for_merchant(deps.storage, &info.sender, id.clone())?;
+    if let Some(assets) = assets {
+        validate_basket(&assets)?;
+        validate_merchant_assets(deps.storage, &p.merchant, &assets)?;
+        p.paid_assets = zero_assets(&assets);
+        p.assets = assets;
+        p.reference_amount = None;
+        // The schedule was defined against the old total
+        p.milestones = vec![];
+    }
+    if let Some(order_id) = order_id {
+        p.order_id = order_id;
+    }
+    payment_requests().save(deps.storage, request_key(&id)?, &p)?;
+    Ok(Response::new().add_attributes(vec![("method", "update_payment_request"), ("id", id.as_str())]))
+}
+
+/// Splits the release of a request into milestones. An empty list releases the whole
+/// request at once again.
+pub fn set_milestones(deps: DepsMut, info: MessageInfo, id: String, milestones: Vec<MilestoneShare>) -> Result<Response, ContractError> {
+    let mut p = load_unpaid_for_merchant(deps.storage, &info.sender, id.clone())?;
+    // Priced requests only know their assets once paid
+    if p.assets.is_empty() {
+        return Err(ContractError::InvalidMilestones {});
+    }
+    p.milestones = if milestones.is_empty() {
+        vec![]
+    } else {
+        resolve_milestones(&p.assets, milestones)?
+    };
+    payment_requests().save(deps.storage, request_key(&id)?, &p)?;
+    Ok(Response::new().add_attributes(vec![("method", "set_milestones"), ("id", id.as_str())]))
+}
+
+pub fn approve_milestone(deps: DepsMut, info: MessageInfo, id: String, milestone: u32) -> Result<Response, ContractError> {
+    match payment_requests().may_load(deps.storage, request_key(&id)?)? {
+        None => Err(ContractError::Std(StdError::NotFound {kind: String::from("payment_request")})),
+        Some(mut p) => {
+            if p.customer != info.sender {
+                return Err(ContractError::Unauthorized {});
+            }
+            if p.status == PaymentRequestStatus::Disputed {
+                return Err(ContractError::Disputed {});
+            }
+            if p.status != PaymentRequestStatus::Paid {
+                return Err(ContractError::Unpaid {});
+            }
+            match p.milestones.get_mut(milestone as usize) {
+                Some(m) if m.status == MilestoneStatus::Pending => m.status = MilestoneStatus::Approved,
+                Some(_) => return Err(ContractError::MilestoneNotPending { milestone }),
+                None => return Err(ContractError::Std(StdError::NotFound {kind: String::from("milestone")})),
+            }
+            payment_requests().save(deps.storage, request_key(&id)?, &p)?;
+            Ok(Response::new().add_attributes(vec![
+                ("method", "approve_milestone"),
+                ("id", id.as_str()),
+                ("milestone", milestone.to_string().as_str()),
+            ]))
+        }
+    }
+}
+
+pub fn pay_into_payment_request(deps: DepsMut, env: Env, info: MessageInfo, id: String) -> Result<Response, ContractError> {
     let customer = info.sender;
-    match PAYMENT_REQUESTS.may_load(deps.storage, id.clone())? {
+    match payment_requests().may_load(deps.storage, request_key(&id)?)? {
         None => Err(ContractError::Std(StdError::NotFound {kind: String::from("payment_request")})),
-        Some(p) => { 
-            /*
-             * TODO: handle refunding tokens that are:
-             * 1. Not the token that we are interested in
-             * 2. Tokens amount remaining after paying
-             */
-            
-            let coin = find_matching_fund(&info.funds, p.asset)?;
-            PAYMENT_REQUESTS.update(deps.storage, id, |p_new_| -> StdResult<_> {
-                match p_new_ {
-                    None => Err(StdError::NotFound {kind: String::from("payment_request")}),
-                    Some(mut p_new) => {
-                        p_new.paid_amount = coin.amount;
-                        p_new.customer = customer.clone();
-                        Ok(p_new)
-                    }
-                }
-            })?;
-            Ok(Response::new())
+        Some(mut p) => {
+            if is_expired(&p, &env) {
+                return Err(ContractError::Expired {});
+            }
+            let offered = coins_to_assets(&info.funds);
+            quote_priced_request(deps.as_ref(), &mut p, &offered)?;
+            let (excess, event) = record_payment(deps.storage, &env, p, &customer, offered)?;
+
+            // Coins of other denoms and whatever was sent above the outstanding amounts
+            let refunds = assets_to_coins(&excess);
+            let mut response = Response::new().add_event(event);
+            if !refunds.is_empty() {
+                response = response.add_message(CosmosMsg::Bank(BankMsg::Send {
+                    to_address: customer.to_string(),
+                    amount: refunds,
+                }));
+            }
+            Ok(response)
         }
     }
 }
 
-fn find_matching_fund(coins: &Vec<Coin>, asset: Asset) -> Result<&Coin, ContractError> {
-    match asset.info {
-        AssetInfo::NativeToken { denom } => {
-            match coins.iter().find(|c| c.denom == denom) {
-                None => Err(ContractError::WrongToken {}),
-                Some(coin) => {
-                    if coin.amount != asset.amount {
-                        return Err(ContractError::WrongAmount {amount: coin.amount});
-                    } else {
-                        return Ok(coin);
-                    }
-                }
+/// Fixes the assets of a request priced in the reference unit from the oracle price of the
+/// first whitelisted asset offered. A payment short of the quote by no more than the
+/// slippage bound is accepted as the full amount.
+fn quote_priced_request(deps: Deps, p: &mut PaymentRequest, offered: &[Asset]) -> Result<(), ContractError> {
+    let reference_amount = match p.reference_amount {
+        Some(amount) if p.assets.is_empty() => amount,
+        _ => return Ok(()),
+    };
+    let state = STATE.load(deps.storage)?;
+    let price_feed = state.price_feed.as_ref().ok_or(ContractError::PriceUnavailable {})?;
+    let offer = offered
+        .iter()
+        .find(|a| !a.amount.is_zero() && state.price_assets.contains(&a.info))
+        .ok_or(ContractError::WrongToken {})?;
+    if let Some(profile) = MERCHANTS.may_load(deps.storage, &p.merchant)? {
+        if !profile.accepted_assets.is_empty() && !profile.accepted_assets.contains(&offer.info) {
+            return Err(ContractError::AssetNotAccepted {});
+        }
+    }
+    let price: PriceResponse = deps.querier.query_wasm_smart(price_feed, &PriceFeedQueryMsg::Price { asset: offer.info.clone() })?;
+    if price.price == Decimal::zero() {
+        return Err(ContractError::PriceUnavailable {});
+    }
+    let due = reference_amount.multiply_ratio(price.price.denominator(), price.price.numerator());
+    let min_amount = due.multiply_ratio(MAX_BPS - state.max_slippage_bps, MAX_BPS);
+    if due.is_zero() || offer.amount < min_amount {
+        return Err(ContractError::SlippageExceeded {});
+    }
+    p.assets = vec![Asset { info: offer.info.clone(), amount: std::cmp::min(offer.amount, due) }];
+    p.paid_assets = zero_assets(&p.assets);
+    Ok(())
+}
+
+/// Applies the `offered` assets from `payer` to the outstanding components of the
+/// request and returns what was not needed, so it can be refunded, along with the
+/// payment event.
+fn record_payment(storage: &mut dyn Storage, env: &Env, mut p: PaymentRequest, payer: &Addr, offered: Vec<Asset>) -> Result<(Vec<Asset>, Event), ContractError> {
+    match p.status {
+        PaymentRequestStatus::Unpaid | PaymentRequestStatus::PartiallyPaid => {},
+        PaymentRequestStatus::Cancelled => return Err(ContractError::Cancelled {}),
+        _ => return Err(ContractError::AlreadyPaid {}),
+    }
+    let mut accepted: Vec<Asset> = vec![];
+    let mut excess: Vec<Asset> = vec![];
+    for offer in offered.into_iter().filter(|a| !a.amount.is_zero()) {
+        let requested = amount_of(&p.assets, &offer.info);
+        let paid = amount_of(&p.paid_assets, &offer.info);
+        let take = std::cmp::min(offer.amount, requested.checked_sub(paid).map_err(StdError::from)?);
+        if !take.is_zero() {
+            let payment = Asset { info: offer.info.clone(), amount: take };
+            add_asset(&mut p.paid_assets, &payment);
+            accepted.push(payment);
+        }
+        if offer.amount > take {
+            excess.push(Asset { info: offer.info, amount: offer.amount.checked_sub(take).map_err(StdError::from)? });
+        }
+    }
+    if accepted.is_empty() {
+        return Err(ContractError::WrongToken {});
+    }
+
+    CONTRIBUTIONS.update(storage, (p.id.as_str(), payer), |c| -> StdResult<_> {
+        let mut contribution = c.unwrap_or_default();
+        for payment in accepted.iter() {
+            add_asset(&mut contribution, payment);
+        }
+        Ok(contribution)
+    })?;
+    if p.status == PaymentRequestStatus::Unpaid {
+        p.customer = payer.clone();
+    }
+    if p.assets.iter().all(|a| amount_of(&p.paid_assets, &a.info) == a.amount) {
+        p.status = PaymentRequestStatus::Paid;
+        p.paid_at = Some(env.block.time);
+    } else {
+        p.status = PaymentRequestStatus::PartiallyPaid;
+    }
+    payment_requests().save(storage, request_key(&p.id)?, &p)?;
+    let fully_paid = p.status == PaymentRequestStatus::Paid;
+    update_stats(storage, &p.merchant, |s| {
+        add_volume(&mut s.paid_volume, &accepted);
+        if fully_paid {
+            s.requests_paid += 1;
+        }
+    })?;
+    update_asset_stats(storage, &accepted, |s, amount| s.paid += amount)?;
+
+    let event = escrow_event("pay", &p, &accepted).add_attribute("payer", payer.as_str());
+    Ok((excess, event))
+}
+
+/// Event emitted for every payment request lifecycle action, so indexers can follow the
+/// escrow from events alone. `asset` and `amount` list the assets involved in the same order.
+fn escrow_event(action: &str, p: &PaymentRequest, assets: &[Asset]) -> Event {
+    let assets: Vec<&Asset> = assets.iter().filter(|a| !a.amount.is_zero()).collect();
+    Event::new("escrow")
+        .add_attribute("action", action)
+        .add_attribute("id", p.id.as_str())
+        .add_attribute("merchant", p.merchant.as_str())
+        .add_attribute("customer", p.customer.as_str())
+        .add_attribute("asset", assets.iter().map(|a| a.info.to_string()).collect::<Vec<String>>().join(","))
+        .add_attribute("amount", assets.iter().map(|a| a.amount.to_string()).collect::<Vec<String>>().join(","))
+}
+
+/// What settling a request owes its merchant, plus the receipt minted to the customer
+/// when the request is closed
+struct Settlement {
+    merchant: Addr,
+    assets: Vec<Asset>,
+    receipt: Option<CosmosMsg>,
+    event: Event,
+}
+
+pub fn settle_payment_request(deps: DepsMut, env: Env, info: MessageInfo, id: String) -> Result<Response, ContractError> {
+    let settlement = settle(deps.storage, &env, &info.sender, &id)?;
+    Ok(pay_merchant(deps.storage, &settlement.merchant, &settlement.assets)?
+        .add_messages(settlement.receipt)
+        .add_attributes(vec![("method", "settle_payment_request"), ("id", id.as_str())])
+        .add_event(settlement.event))
+}
+
+/// Settles several requests of the same customer at once. Payouts to the same merchant
+/// are aggregated so each merchant gets a single transfer per asset.
+pub fn settle_payment_requests(deps: DepsMut, env: Env, info: MessageInfo, ids: Vec<String>) -> Result<Response, ContractError> {
+    let mut payouts: Vec<(Addr, Vec<Asset>)> = vec![];
+    let mut receipts: Vec<CosmosMsg> = vec![];
+    let mut events: Vec<Event> = vec![];
+    for (index, id) in ids.iter().enumerate() {
+        let settlement = settle(deps.storage, &env, &info.sender, id)
+            .map_err(|e| ContractError::BatchItem { index: index as u32, error: e.to_string() })?;
+        match payouts.iter_mut().find(|(m, _)| *m == settlement.merchant) {
+            Some((_, assets)) => settlement.assets.iter().for_each(|a| add_asset(assets, a)),
+            None => payouts.push((settlement.merchant, settlement.assets)),
+        }
+        receipts.extend(settlement.receipt);
+        events.push(settlement.event);
+    }
+
+    let mut response = Response::new().add_attributes(vec![("method", "settle_payment_requests"), ("ids", ids.join(",").as_str())]);
+    for (merchant, assets) in payouts.iter() {
+        let payout = pay_merchant(deps.storage, merchant, assets)?;
+        response = response.add_submessages(payout.messages).add_attributes(payout.attributes);
+    }
+    Ok(response.add_messages(receipts).add_events(events))
+}
+
+/// Settles a paid request, or releases its approved milestones, on behalf of its customer
+fn settle(storage: &mut dyn Storage, env: &Env, customer: &Addr, id: &str) -> Result<Settlement, ContractError> {
+    match payment_requests().may_load(storage, request_key(id)?)? {
+        None => Err(ContractError::Std(StdError::NotFound {kind: String::from("payment_request")})),
+        Some(p) => {
+            if p.customer != *customer {
+                return Err(ContractError::Unauthorized {});
+            }
+            if p.hashlock.is_some() {
+                return Err(ContractError::HashLocked {});
+            }
+            if p.status == PaymentRequestStatus::Disputed {
+                return Err(ContractError::Disputed {});
+            }
+            if p.status != PaymentRequestStatus::Paid {
+                return Err(ContractError::Unpaid {});
+            }
+            if !p.milestones.is_empty() {
+                return release_approved_milestones(storage, env, p);
             }
+            let receipt = close_payment_request(storage, env, &p)?;
+            let event = escrow_event("settle", &p, &p.paid_assets);
+            Ok(Settlement { merchant: p.merchant, assets: p.paid_assets, receipt, event })
+        }
+    }
+}
+
+/// Removes a settled request and builds the receipt for its customer, when the
+/// receipt collection is set up
+fn close_payment_request(storage: &mut dyn Storage, env: &Env, p: &PaymentRequest) -> StdResult<Option<CosmosMsg>> {
+    payment_requests().remove(storage, request_key(&p.id)?)?;
+    clear_contributions(storage, &p.id)?;
+    update_stats(storage, &p.merchant, |s| s.requests_settled += 1)?;
+
+    let receipt_contract = match STATE.load(storage)?.receipt_contract {
+        None => return Ok(None),
+        Some(receipt_contract) => receipt_contract,
+    };
+    let mint = Cw721ExecuteMsg::Mint(MintMsg {
+        token_id: p.id.clone(),
+        owner: p.customer.to_string(),
+        token_uri: None,
+        extension: ReceiptMetadata {
+            payment_request_id: p.id.clone(),
+            merchant: p.merchant.clone(),
+            order_id: p.order_id.clone(),
+            assets: p.paid_assets.clone(),
+            settled_at: env.block.time,
         },
-        AssetInfo::Token { contract_addr: _ } => Err(ContractError::WrongToken {})
+    });
+    Ok(Some(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: receipt_contract.to_string(),
+        msg: to_binary(&mint)?,
+        funds: vec![],
+    })))
+}
+
+/// Settles a paid request on the customer's behalf once the confirmation window has
+/// passed without a dispute. Anyone can call it, so merchants or keepers are not left
+/// waiting on an inactive customer. Pending milestones are released as well. Disabled
+/// while the confirmation window is `0`.
+pub fn release(deps: DepsMut, env: Env, id: String) -> Result<Response, ContractError> {
+    match payment_requests().may_load(deps.storage, request_key(&id)?)? {
+        None => Err(ContractError::Std(StdError::NotFound {kind: String::from("payment_request")})),
+        Some(mut p) => {
+            if p.hashlock.is_some() {
+                return Err(ContractError::HashLocked {});
+            }
+            if p.status == PaymentRequestStatus::Disputed {
+                return Err(ContractError::Disputed {});
+            }
+            let paid_at = match (&p.status, p.paid_at) {
+                (PaymentRequestStatus::Paid, Some(paid_at)) => paid_at,
+                (PaymentRequestStatus::Paid, None) => return Err(ContractError::ReleaseDisabled {}),
+                _ => return Err(ContractError::Unpaid {}),
+            };
+            let state = STATE.load(deps.storage)?;
+            if state.confirmation_window == 0 {
+                return Err(ContractError::ReleaseDisabled {});
+            }
+            if env.block.time < paid_at.plus_seconds(state.confirmation_window) {
+                return Err(ContractError::ConfirmationPending {});
+            }
+            let settlement = if p.milestones.is_empty() {
+                let receipt = close_payment_request(deps.storage, &env, &p)?;
+                let event = escrow_event("settle", &p, &p.paid_assets);
+                Settlement { merchant: p.merchant, assets: p.paid_assets, receipt, event }
+            } else {
+                for milestone in p.milestones.iter_mut().filter(|m| m.status == MilestoneStatus::Pending) {
+                    milestone.status = MilestoneStatus::Approved;
+                }
+                release_approved_milestones(deps.storage, &env, p)?
+            };
+
+            Ok(pay_merchant(deps.storage, &settlement.merchant, &settlement.assets)?
+                .add_messages(settlement.receipt)
+                .add_attributes(vec![("method", "release"), ("id", id.as_str())])
+                .add_event(settlement.event))
+        }
     }
 }
 
-pub fn settle_payment_request(deps: DepsMut, info: MessageInfo, id: String) -> Result<Response, ContractError> {
-    let customer = info.sender;
-    match PAYMENT_REQUESTS.may_load(deps.storage, id.clone())? {
+/// Marks every approved milestone of a request as released. The request is closed once
+/// its last milestone is released.
+fn release_approved_milestones(storage: &mut dyn Storage, env: &Env, mut p: PaymentRequest) -> Result<Settlement, ContractError> {
+    let mut released: Vec<Asset> = vec![];
+    for milestone in p.milestones.iter_mut().filter(|m| m.status == MilestoneStatus::Approved) {
+        for asset in milestone.assets.iter() {
+            add_asset(&mut released, asset);
+        }
+        milestone.status = MilestoneStatus::Released;
+    }
+    if released.is_empty() {
+        return Err(ContractError::NoApprovedMilestones {});
+    }
+    let receipt = if p.milestones.iter().all(|m| m.status == MilestoneStatus::Released) {
+        close_payment_request(storage, env, &p)?
+    } else {
+        payment_requests().save(storage, request_key(&p.id)?, &p)?;
+        None
+    };
+    let event = escrow_event("settle", &p, &released);
+    Ok(Settlement { merchant: p.merchant, assets: released, receipt, event })
+}
+
+/// Paid assets that were not released to the merchant through a milestone yet
+fn unreleased_assets(p: &PaymentRequest) -> StdResult<Vec<Asset>> {
+    let mut released = zero_assets(&p.paid_assets);
+    for milestone in p.milestones.iter().filter(|m| m.status == MilestoneStatus::Released) {
+        for asset in milestone.assets.iter() {
+            add_asset(&mut released, asset);
+        }
+    }
+    p.paid_assets
+        .iter()
+        .map(|a| Ok(Asset { info: a.info.clone(), amount: a.amount.checked_sub(amount_of(&released, &a.info))? }))
+        .collect()
+}
+
+/// Releases a funded HTLC request to its merchant. Anyone holding the preimage can
+/// claim before the timeout; the preimage is emitted so the other leg of a swap can
+/// be claimed with it.
+pub fn claim_htlc(deps: DepsMut, env: Env, id: String, preimage: Binary) -> Result<Response, ContractError> {
+    match payment_requests().may_load(deps.storage, request_key(&id)?)? {
         None => Err(ContractError::Std(StdError::NotFound {kind: String::from("payment_request")})),
-        Some(p) => { 
-            if p.customer != customer {
+        Some(p) => {
+            let hashlock = match &p.hashlock {
+                None => return Err(ContractError::InvalidPreimage {}),
+                Some(hashlock) => hashlock,
+            };
+            if Sha256::digest(preimage.as_slice()).as_slice() != hashlock.as_slice() {
+                return Err(ContractError::InvalidPreimage {});
+            }
+            if is_expired(&p, &env) {
+                return Err(ContractError::Expired {});
+            }
+            if p.status != PaymentRequestStatus::Paid {
+                return Err(ContractError::Unpaid {});
+            }
+            let receipt = close_payment_request(deps.storage, &env, &p)?;
+
+            Ok(pay_merchant(deps.storage, &p.merchant, &p.paid_assets)?
+                .add_messages(receipt)
+                .add_attributes(vec![("method", "claim_htlc"), ("id", id.as_str())])
+                .add_event(escrow_event("settle", &p, &p.paid_assets))
+                .add_attribute("preimage", preimage.to_base64()))
+        }
+    }
+}
+
+/// Returns the funds of a request that was never settled to its payers once it has
+/// expired. Any payer can trigger the refund.
+pub fn reclaim(deps: DepsMut, env: Env, info: MessageInfo, id: String) -> Result<Response, ContractError> {
+    match payment_requests().may_load(deps.storage, request_key(&id)?)? {
+        None => Err(ContractError::Std(StdError::NotFound {kind: String::from("payment_request")})),
+        Some(p) => {
+            if !CONTRIBUTIONS.has(deps.storage, (id.as_str(), &info.sender)) {
+                return Err(ContractError::Unauthorized {});
+            }
+            if p.status == PaymentRequestStatus::Disputed {
+                return Err(ContractError::Disputed {});
+            }
+            if !is_expired(&p, &env) {
+                return Err(ContractError::NotExpired {});
+            }
+            payment_requests().remove(deps.storage, request_key(&id)?)?;
+            let refund = unreleased_assets(&p)?;
+            let msgs = refund_contributors(deps.storage, &p, &refund)?;
+
+            Ok(Response::new()
+                .add_messages(msgs)
+                .add_attributes(vec![("method", "reclaim"), ("id", id.as_str())])
+                .add_event(escrow_event("refund", &p, &refund)))
+        }
+    }
+}
+
+pub fn raise_dispute(deps: DepsMut, env: Env, info: MessageInfo, id: String, reason: String) -> Result<Response, ContractError> {
+    match payment_requests().may_load(deps.storage, request_key(&id)?)? {
+        None => Err(ContractError::Std(StdError::NotFound {kind: String::from("payment_request")})),
+        Some(mut p) => {
+            if info.sender != p.merchant && info.sender != p.customer {
                 return Err(ContractError::Unauthorized {});
             }
-            if p.paid_amount != p.asset.amount {
-                return Err(ContractError::Unpaid {}); 
+            if p.hashlock.is_some() {
+                return Err(ContractError::HashLocked {});
+            }
+            if p.status == PaymentRequestStatus::Disputed {
+                return Err(ContractError::Disputed {});
+            }
+            if p.status != PaymentRequestStatus::Paid {
+                return Err(ContractError::Unpaid {});
+            }
+            let dispute = Dispute {
+                id: id.clone(),
+                raised_by: info.sender,
+                reason,
+                raised_at: env.block.time,
+                resolution: None,
+            };
+            DISPUTES.save(deps.storage, id.clone(), &dispute)?;
+            p.status = PaymentRequestStatus::Disputed;
+            payment_requests().save(deps.storage, request_key(&id)?, &p)?;
+
+            Ok(Response::new().add_attributes(vec![("method", "raise_dispute"), ("id", id.as_str())]))
+        }
+    }
+}
+
+/// Splits the disputed funds between the merchant and the customer: the merchant
+/// receives `merchant_assets` and the payers get the rest back. Only the shop or the
+/// configured arbiter can resolve a dispute.
+pub fn resolve_dispute(deps: DepsMut, info: MessageInfo, id: String, merchant_assets: Vec<Asset>) -> Result<Response, ContractError> {
+    let state = STATE.load(deps.storage)?;
+    if info.sender != state.shop && Some(info.sender.clone()) != state.arbiter {
+        return Err(ContractError::Unauthorized {});
+    }
+    match payment_requests().may_load(deps.storage, request_key(&id)?)? {
+        None => Err(ContractError::Std(StdError::NotFound {kind: String::from("payment_request")})),
+        Some(p) => {
+            if p.status != PaymentRequestStatus::Disputed {
+                return Err(ContractError::NotDisputed {});
+            }
+            // Milestones already released are no longer in escrow
+            let disputed = unreleased_assets(&p)?;
+            let mut merchant_share = zero_assets(&disputed);
+            for asset in merchant_assets.iter() {
+                if asset.amount > amount_of(&disputed, &asset.info) {
+                    return Err(ContractError::WrongAmount {amount: asset.amount});
+                }
+                add_asset(&mut merchant_share, asset);
             }
-            PAYMENT_REQUESTS.remove(deps.storage, id.clone());
+            let customer_share: Vec<Asset> = disputed
+                .iter()
+                .map(|a| -> StdResult<_> {
+                    Ok(Asset {
+                        info: a.info.clone(),
+                        amount: a.amount.checked_sub(amount_of(&merchant_share, &a.info))?,
+                    })
+                })
+                .collect::<StdResult<_>>()?;
+            DISPUTES.update(deps.storage, id.clone(), |d| -> StdResult<_> {
+                match d {
+                    None => Err(StdError::NotFound {kind: String::from("dispute")}),
+                    Some(mut dispute) => {
+                        dispute.resolution = Some(DisputeResolution {
+                            resolved_by: info.sender.clone(),
+                            merchant_assets: merchant_share.clone(),
+                            customer_assets: customer_share.clone(),
+                        });
+                        Ok(dispute)
+                    }
+                }
+            })?;
+            payment_requests().remove(deps.storage, request_key(&id)?)?;
+
+            let refunds = refund_contributors(deps.storage, &p, &customer_share)?;
+            let events: Vec<Event> = [("settle", &merchant_share), ("refund", &customer_share)]
+                .iter()
+                .filter(|(_, share)| share.iter().any(|a| !a.amount.is_zero()))
+                .map(|(action, share)| escrow_event(action, &p, share))
+                .collect();
+            Ok(pay_merchant(deps.storage, &p.merchant, &merchant_share)?
+                .add_messages(refunds)
+                .add_attributes(vec![("method", "resolve_dispute"), ("id", id.as_str())])
+                .add_events(events))
+        }
+    }
+}
+
+fn load_contributions(storage: &dyn Storage, id: &str) -> StdResult<Vec<Contribution>> {
+    CONTRIBUTIONS
+        .prefix(id)
+        .range(storage, None, None, Order::Ascending)
+        .map(|item| {
+            let (payer, assets) = item?;
+            Ok(Contribution { payer: Addr::unchecked(String::from_utf8(payer)?), assets })
+        })
+        .collect()
+}
+
+fn clear_contributions(storage: &mut dyn Storage, id: &str) -> StdResult<()> {
+    for contribution in load_contributions(storage, id)? {
+        CONTRIBUTIONS.remove(storage, (id, &contribution.payer));
+    }
+    Ok(())
+}
+
+/// Refunds `refund` out of the request's funds to its payers, pro rata to what each
+/// of them contributed to every asset. Rounding dust goes to the last payer of an asset.
+fn refund_contributors(storage: &mut dyn Storage, p: &PaymentRequest, refund: &[Asset]) -> StdResult<Vec<CosmosMsg>> {
+    let contributions = load_contributions(storage, &p.id)?;
+    clear_contributions(storage, &p.id)?;
+    if refund.iter().any(|a| !a.amount.is_zero()) {
+        update_stats(storage, &p.merchant, |s| {
+            s.requests_refunded += 1;
+            add_volume(&mut s.refunded_volume, refund);
+        })?;
+        update_asset_stats(storage, refund, |s, amount| s.refunded += amount)?;
+    }
 
-            match p.asset.info {
-                AssetInfo::NativeToken {denom} => Ok(Response::new().add_message(CosmosMsg::Bank(BankMsg::Send {
-                        amount: coins(p.paid_amount.u128(), denom),
-                        to_address: p.merchant.to_string(),
-                }))),
-                AssetInfo::Token {contract_addr } => Ok(Response::new().add_message(CosmosMsg::Wasm(WasmMsg::Execute {
-                    contract_addr: contract_addr,
-                    funds: vec!(),
-                    msg: to_binary(&Cw20ExecuteMsg::Transfer {
-                        amount: p.paid_amount,
-                        recipient: p.merchant.to_string(),
-                    })?
-                })))
+    let mut remaining: Vec<Asset> = refund.to_vec();
+    let mut msgs: Vec<CosmosMsg> = vec![];
+    for (i, contribution) in contributions.iter().enumerate() {
+        for asset in remaining.iter_mut() {
+            let contributed = amount_of(&contribution.assets, &asset.info);
+            if contributed.is_zero() {
+                continue;
             }
+            let is_last_payer = contributions[i + 1..]
+                .iter()
+                .all(|c| amount_of(&c.assets, &asset.info).is_zero());
+            let share = if is_last_payer {
+                asset.amount
+            } else {
+                contributed.multiply_ratio(amount_of(refund, &asset.info), amount_of(&p.paid_assets, &asset.info))
+            };
+            asset.amount = asset.amount.checked_sub(share)?;
+            if !share.is_zero() {
+                msgs.push(Asset { info: asset.info.clone(), amount: share }.into_msg(contribution.payer.clone())?);
+            }
+        }
+    }
+    Ok(msgs)
+}
+
+/// Applies `update` to the merchant's statistics and to the protocol-wide totals
+fn update_stats<F: Fn(&mut Stats)>(storage: &mut dyn Storage, merchant: &Addr, update: F) -> StdResult<()> {
+    let mut stats = MERCHANT_STATS.may_load(storage, merchant)?.unwrap_or_default();
+    update(&mut stats);
+    MERCHANT_STATS.save(storage, merchant, &stats)?;
+    let mut totals = TOTAL_STATS.may_load(storage)?.unwrap_or_default();
+    update(&mut totals);
+    TOTAL_STATS.save(storage, &totals)
+}
+
+fn update_asset_stats<F: Fn(&mut AssetStats, Uint128)>(storage: &mut dyn Storage, assets: &[Asset], update: F) -> StdResult<()> {
+    for asset in assets.iter().filter(|a| !a.amount.is_zero()) {
+        ASSET_STATS.update(storage, asset.info.to_string().as_str(), |s| -> StdResult<_> {
+            let mut stats = s.unwrap_or_default();
+            update(&mut stats, asset.amount);
+            Ok(stats)
+        })?;
+    }
+    Ok(())
+}
+
+fn add_volume(volume: &mut Vec<Asset>, assets: &[Asset]) {
+    for asset in assets.iter().filter(|a| !a.amount.is_zero()) {
+        add_asset(volume, asset);
+    }
+}
+
+/// A payment request without an expiry never expires
+fn is_expired(p: &PaymentRequest, env: &Env) -> bool {
+    match &p.expires {
+        None => false,
+        Some(expires) => expires.is_expired(&env.block),
+    }
+}
+
+/// Opens a swap offer with the asset the maker deposited. `ask` is the price of the
+/// whole deposit; with partial fills takers can buy part of it at the same rate.
+pub fn create_offer(deps: DepsMut, maker: Addr, offer: Asset, ask: Asset, counterparty: Option<String>, partial_fills: bool) -> Result<Response, ContractError> {
+    if offer.amount.is_zero() || ask.amount.is_zero() || offer.info.equal(&ask.info) {
+        return Err(ContractError::InvalidOffer {});
+    }
+    let counterparty = match counterparty {
+        None => None,
+        Some(counterparty) => Some(deps.api.addr_validate(&counterparty)?),
+    };
+    let id = LAST_OFFER_ID.may_load(deps.storage)?.unwrap_or_default() + Uint64::from(1u64);
+    LAST_OFFER_ID.save(deps.storage, &id)?;
+    let offer = Offer {
+        id: id.to_string(),
+        maker,
+        offer,
+        ask,
+        counterparty,
+        partial_fills,
+        filled: Uint128::zero(),
+    };
+    OFFERS.save(deps.storage, offer_key(&offer.id)?, &offer)?;
+    Ok(Response::new().add_attributes(vec![("method", "create_offer"), ("id", offer.id.as_str())]))
+}
+
+/// Swaps the taker's `deposit` against the offered asset at the offer's rate. Both legs
+/// are released in the same transaction and anything above the outstanding ask is
+/// returned to the taker.
+pub fn fill_offer(deps: DepsMut, taker: Addr, id: String, deposit: Asset) -> Result<Response, ContractError> {
+    let mut offer = match OFFERS.may_load(deps.storage, offer_key(&id)?)? {
+        None => return Err(ContractError::Std(StdError::NotFound {kind: String::from("offer")})),
+        Some(offer) => offer,
+    };
+    if let Some(counterparty) = &offer.counterparty {
+        if *counterparty != taker {
+            return Err(ContractError::Unauthorized {});
+        }
+    }
+    if !deposit.info.equal(&offer.ask.info) || deposit.amount.is_zero() {
+        return Err(ContractError::WrongToken {});
+    }
+    let outstanding = offer.ask.amount.checked_sub(offer.filled).map_err(StdError::from)?;
+    if deposit.amount < outstanding && !offer.partial_fills {
+        return Err(ContractError::PartialFillNotAllowed {});
+    }
+    let paid = std::cmp::min(deposit.amount, outstanding);
+    let released_before = offer.released();
+    offer.filled += paid;
+    let released = offer.released().checked_sub(released_before).map_err(StdError::from)?;
+    if released.is_zero() {
+        return Err(ContractError::FillTooSmall {});
+    }
+
+    let excess = deposit.amount.checked_sub(paid).map_err(StdError::from)?;
+    let response = Response::new()
+        .add_messages(transfer_msgs(&[Asset { info: offer.ask.info.clone(), amount: paid }], &offer.maker)?)
+        .add_messages(transfer_msgs(&[Asset { info: offer.offer.info.clone(), amount: released }], &taker)?)
+        .add_messages(transfer_msgs(&[Asset { info: deposit.info, amount: excess }], &taker)?)
+        .add_attributes(vec![("method", "fill_offer"), ("id", id.as_str())]);
+    if offer.filled == offer.ask.amount {
+        OFFERS.remove(deps.storage, offer_key(&id)?);
+    } else {
+        OFFERS.save(deps.storage, offer_key(&id)?, &offer)?;
+    }
+    Ok(response)
+}
+
+/// Returns the unsold part of an offer to its maker
+pub fn cancel_offer(deps: DepsMut, info: MessageInfo, id: String) -> Result<Response, ContractError> {
+    let offer = match OFFERS.may_load(deps.storage, offer_key(&id)?)? {
+        None => return Err(ContractError::Std(StdError::NotFound {kind: String::from("offer")})),
+        Some(offer) => offer,
+    };
+    if info.sender != offer.maker {
+        return Err(ContractError::Unauthorized {});
+    }
+    OFFERS.remove(deps.storage, offer_key(&id)?);
+    let remaining = offer.offer.amount.checked_sub(offer.released()).map_err(StdError::from)?;
+
+    Ok(Response::new()
+        .add_messages(transfer_msgs(&[Asset { info: offer.offer.info, amount: remaining }], &offer.maker)?)
+        .add_attributes(vec![("method", "cancel_offer"), ("id", id.as_str())]))
+}
+
+/// Locks the customer's `assets` for `merchant` until the merchant accepts or rejects
+/// the intent, or it expires
+pub fn create_payment_intent(deps: DepsMut, env: Env, customer: Addr, merchant: String, description: String, assets: Vec<Asset>, expires: Expiration) -> Result<Response, ContractError> {
+    validate_basket(&assets)?;
+    // The customer's only way back to the funds is the timeout
+    if let Expiration::Never {} = expires {
+        return Err(ContractError::InvalidExpiration {});
+    }
+    if expires.is_expired(&env.block) {
+        return Err(ContractError::Expired {});
+    }
+    let id = LAST_INTENT_ID.may_load(deps.storage)?.unwrap_or_default() + Uint64::from(1u64);
+    LAST_INTENT_ID.save(deps.storage, &id)?;
+    let intent = PaymentIntent {
+        id: id.to_string(),
+        customer,
+        merchant: deps.api.addr_validate(&merchant)?,
+        description,
+        assets,
+        expires,
+    };
+    payment_intents().save(deps.storage, intent_key(&intent.id)?, &intent)?;
+    Ok(Response::new().add_attributes(vec![("method", "create_payment_intent"), ("id", intent.id.as_str())]))
+}
+
+fn load_payment_intent(storage: &dyn Storage, id: String) -> Result<PaymentIntent, ContractError> {
+    match payment_intents().may_load(storage, intent_key(&id)?)? {
+        None => Err(ContractError::Std(StdError::NotFound {kind: String::from("payment_intent")})),
+        Some(intent) => Ok(intent),
+    }
+}
+
+/// Turns the intent into a payment request already paid by the customer, which then
+/// follows the usual settlement flow
+pub fn accept_payment_intent(deps: DepsMut, env: Env, info: MessageInfo, id: String) -> Result<Response, ContractError> {
+    let intent = load_payment_intent(deps.storage, id.clone())?;
+    if info.sender != intent.merchant {
+        return Err(ContractError::Unauthorized {});
+    }
+    if intent.expires.is_expired(&env.block) {
+        return Err(ContractError::Expired {});
+    }
+    payment_intents().remove(deps.storage, intent_key(&id)?)?;
+    let p = save_new_payment_request(deps.storage, intent.merchant, intent.assets.clone(), intent.description, None, None, None)?;
+    let payment_request_id = p.id.clone();
+    let created = escrow_event("create", &p, &p.assets);
+    let (_, paid) = record_payment(deps.storage, &env, p, &intent.customer, intent.assets)?;
+
+    Ok(Response::new()
+        .add_attributes(vec![("method", "accept_payment_intent"), ("id", id.as_str()), ("payment_request_id", payment_request_id.as_str())])
+        .add_events(vec![created, paid]))
+}
+
+pub fn reject_payment_intent(deps: DepsMut, info: MessageInfo, id: String) -> Result<Response, ContractError> {
+    let intent = load_payment_intent(deps.storage, id.clone())?;
+    if info.sender != intent.merchant {
+        return Err(ContractError::Unauthorized {});
+    }
+    payment_intents().remove(deps.storage, intent_key(&id)?)?;
+    Ok(Response::new()
+        .add_messages(transfer_msgs(&intent.assets, &intent.customer)?)
+        .add_attributes(vec![("method", "reject_payment_intent"), ("id", id.as_str())]))
+}
+
+/// Returns the funds of an intent the merchant let expire. Anyone can trigger it.
+pub fn reclaim_payment_intent(deps: DepsMut, env: Env, id: String) -> Result<Response, ContractError> {
+    let intent = load_payment_intent(deps.storage, id.clone())?;
+    if !intent.expires.is_expired(&env.block) {
+        return Err(ContractError::NotExpired {});
+    }
+    payment_intents().remove(deps.storage, intent_key(&id)?)?;
+    Ok(Response::new()
+        .add_messages(transfer_msgs(&intent.assets, &intent.customer)?)
+        .add_attributes(vec![("method", "reclaim_payment_intent"), ("id", id.as_str())]))
+}
+
+pub fn create_plan(deps: DepsMut, info: MessageInfo, asset: Asset, period: u64) -> Result<Response, ContractError> {
+    let token = match &asset.info {
+        AssetInfo::Token { contract_addr } => deps.api.addr_validate(contract_addr)?,
+        AssetInfo::NativeToken { .. } => return Err(ContractError::InvalidPlan {}),
+    };
+    if asset.amount.is_zero() || period == 0 {
+        return Err(ContractError::InvalidPlan {});
+    }
+    let id = LAST_PLAN_ID.may_load(deps.storage)?.unwrap_or_default() + Uint64::from(1u64);
+    LAST_PLAN_ID.save(deps.storage, &id)?;
+    let plan = Plan {
+        id: id.to_string(),
+        merchant: info.sender,
+        asset: Asset { info: AssetInfo::Token { contract_addr: token.to_string() }, amount: asset.amount },
+        period,
+    };
+    PLANS.save(deps.storage, plan_key(&plan.id)?, &plan)?;
+    Ok(Response::new().add_attributes(vec![("method", "create_plan"), ("plan_id", plan.id.as_str())]))
+}
+
+/// Whether the escrow may currently pull `amount` of `token` from `owner`
+fn can_pull(deps: Deps, env: &Env, token: &str, owner: &Addr, amount: Uint128) -> StdResult<bool> {
+    let allowance: AllowanceResponse = deps.querier.query_wasm_smart(token, &Cw20QueryMsg::Allowance {
+        owner: owner.to_string(),
+        spender: env.contract.address.to_string(),
+    })?;
+    if allowance.expires.is_expired(&env.block) || allowance.allowance < amount {
+        return Ok(false);
+    }
+    let balance: BalanceResponse = deps.querier.query_wasm_smart(token, &Cw20QueryMsg::Balance {
+        address: owner.to_string(),
+    })?;
+    Ok(balance.balance >= amount)
+}
+
+/// Pulls one period of `plan` from `subscriber`, splitting the platform fee to the shop
+fn charge_period(storage: &dyn Storage, plan: &Plan, subscriber: &Addr) -> StdResult<Vec<CosmosMsg>> {
+    let state = STATE.load(storage)?;
+    let fee_bps = merchant_fee_bps(storage, &state, &plan.merchant)?;
+    let (net, fees) = split_fee(std::slice::from_ref(&plan.asset), fee_bps)?;
+    let token = plan.asset.info.to_string();
+    let payout = payout_address(storage, &plan.merchant)?;
+    let mut msgs = vec![];
+    if !net[0].amount.is_zero() {
+        msgs.push(transfer_from_msg(&token, subscriber, &payout, net[0].amount)?);
+    }
+    if !fees[0].amount.is_zero() {
+        msgs.push(transfer_from_msg(&token, subscriber, &state.shop, fees[0].amount)?);
+    }
+    Ok(msgs)
+}
+
+/// Starts a subscription and collects its first period. The subscriber must have
+/// granted the escrow a cw20 allowance beforehand.
+pub fn subscribe(deps: DepsMut, env: Env, info: MessageInfo, plan_id: String) -> Result<Response, ContractError> {
+    let plan = PLANS.load(deps.storage, plan_key(&plan_id)?)?;
+    let token = plan.asset.info.to_string();
+    if !can_pull(deps.as_ref(), &env, &token, &info.sender, plan.asset.amount)? {
+        return Err(ContractError::InsufficientAllowance {});
+    }
+    let id = LAST_SUBSCRIPTION_ID.may_load(deps.storage)?.unwrap_or_default() + Uint64::from(1u64);
+    LAST_SUBSCRIPTION_ID.save(deps.storage, &id)?;
+    let subscription = Subscription {
+        id: id.to_string(),
+        plan_id: plan.id.clone(),
+        merchant: plan.merchant.clone(),
+        subscriber: info.sender.clone(),
+        status: SubscriptionStatus::Active,
+        next_collection: env.block.time.plus_seconds(plan.period),
+        missed_payments: 0,
+    };
+    subscriptions().save(deps.storage, subscription_key(&subscription.id)?, &subscription)?;
+
+    Ok(Response::new()
+        .add_messages(charge_period(deps.storage, &plan, &info.sender)?)
+        .add_attributes(vec![("method", "subscribe"), ("subscription_id", subscription.id.as_str())]))
+}
+
+/// Collects the next due period of a subscription. Anyone can trigger it. When the
+/// subscriber's allowance or balance falls short the period is recorded as missed,
+/// and the subscription lapses after `MAX_MISSED_PAYMENTS` consecutive misses.
+pub fn collect(deps: DepsMut, env: Env, subscription_id: String) -> Result<Response, ContractError> {
+    let mut subscription = subscriptions().load(deps.storage, subscription_key(&subscription_id)?)?;
+    if subscription.status != SubscriptionStatus::Active {
+        return Err(ContractError::SubscriptionInactive {});
+    }
+    if env.block.time < subscription.next_collection {
+        return Err(ContractError::NotDue {});
+    }
+    let plan = PLANS.load(deps.storage, plan_key(&subscription.plan_id)?)?;
+    let token = plan.asset.info.to_string();
+    subscription.next_collection = subscription.next_collection.plus_seconds(plan.period);
+
+    let mut response = Response::new().add_attributes(vec![("method", "collect"), ("subscription_id", subscription.id.as_str())]);
+    if can_pull(deps.as_ref(), &env, &token, &subscription.subscriber, plan.asset.amount)? {
+        subscription.missed_payments = 0;
+        response = response
+            .add_messages(charge_period(deps.storage, &plan, &subscription.subscriber)?)
+            .add_attribute("collected", plan.asset.to_string());
+    } else {
+        subscription.missed_payments += 1;
+        if subscription.missed_payments >= MAX_MISSED_PAYMENTS {
+            subscription.status = SubscriptionStatus::Lapsed;
         }
+        response = response.add_attribute("missed_payments", subscription.missed_payments.to_string());
+    }
+    subscriptions().save(deps.storage, subscription_key(&subscription.id)?, &subscription)?;
+    Ok(response)
+}
+
+pub fn cancel_subscription(deps: DepsMut, info: MessageInfo, subscription_id: String) -> Result<Response, ContractError> {
+    let mut subscription = subscriptions().load(deps.storage, subscription_key(&subscription_id)?)?;
+    if info.sender != subscription.subscriber && info.sender != subscription.merchant {
+        return Err(ContractError::Unauthorized {});
+    }
+    if subscription.status != SubscriptionStatus::Active {
+        return Err(ContractError::SubscriptionInactive {});
     }
+    subscription.status = SubscriptionStatus::Cancelled;
+    subscriptions().save(deps.storage, subscription_key(&subscription.id)?, &subscription)?;
+    Ok(Response::new().add_attributes(vec![("method", "cancel_subscription"), ("subscription_id", subscription.id.as_str())]))
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
+        QueryMsg::GetConfig {} => to_binary(&ConfigResponse { config: STATE.load(deps.storage)? }),
+        QueryMsg::GetMerchantFee { merchant } => to_binary(&get_merchant_fee(deps, merchant)?),
+        QueryMsg::GetBalances { merchant } => to_binary(&get_balances(deps, merchant)?),
+        QueryMsg::GetMerchant { merchant } => to_binary(&get_merchant(deps, merchant)?),
+        QueryMsg::ListMerchants { start_after, limit } => to_binary(&list_merchants(deps, start_after, limit)?),
         QueryMsg::GetPaymentRequestById { id } => to_binary(&get_payment_request_by_id(deps, id)?),
+        QueryMsg::ListByMerchant { merchant, start_after, limit } => to_binary(&list_by_merchant(deps, merchant, start_after, limit)?),
+        QueryMsg::ListByCustomer { customer, start_after, limit } => to_binary(&list_by_customer(deps, customer, start_after, limit)?),
+        QueryMsg::ListByStatus { status, start_after, limit } => to_binary(&list_by_status(deps, status, start_after, limit)?),
+        QueryMsg::GetMilestones { id } => to_binary(&get_milestones(deps, id)?),
+        QueryMsg::ListContributions { id, start_after, limit } => to_binary(&list_contributions(deps, id, start_after, limit)?),
+        QueryMsg::GetDispute { id } => to_binary(&get_dispute(deps, id)?),
+        QueryMsg::ListOpenDisputes { start_after, limit } => to_binary(&list_open_disputes(deps, start_after, limit)?),
+        QueryMsg::GetOffer { id } => to_binary(&get_offer(deps, id)?),
+        QueryMsg::ListOffers { start_after, limit } => to_binary(&list_offers(deps, start_after, limit)?),
+        QueryMsg::GetPaymentIntent { id } => to_binary(&get_payment_intent(deps, id)?),
+        QueryMsg::ListIntentsByMerchant { merchant, start_after, limit } => to_binary(&list_intents_by_merchant(deps, merchant, start_after, limit)?),
+        QueryMsg::ListIntentsByCustomer { customer, start_after, limit } => to_binary(&list_intents_by_customer(deps, customer, start_after, limit)?),
+        QueryMsg::GetPlan { plan_id } => to_binary(&get_plan(deps, plan_id)?),
+        QueryMsg::GetSubscription { subscription_id } => to_binary(&get_subscription(deps, subscription_id)?),
+        QueryMsg::ListSubscriptionsByMerchant { merchant, start_after, limit } => to_binary(&list_subscriptions_by_merchant(deps, merchant, start_after, limit)?),
+        QueryMsg::ListSubscriptionsBySubscriber { subscriber, start_after, limit } => to_binary(&list_subscriptions_by_subscriber(deps, subscriber, start_after, limit)?),
+        QueryMsg::GetMerchantStats { merchant } => to_binary(&get_merchant_stats(deps, merchant)?),
+        QueryMsg::GetAssetStats { asset } => to_binary(&AssetStatsResponse { stats: ASSET_STATS.may_load(deps.storage, asset.to_string().as_str())?.unwrap_or_default() }),
+        QueryMsg::GetTotalStats {} => to_binary(&StatsResponse { stats: TOTAL_STATS.may_load(deps.storage)?.unwrap_or_default() }),
+    }
+}
+
+pub fn get_merchant_fee(deps: Deps, merchant: String) -> StdResult<MerchantFeeResponse> {
+    let merchant = deps.api.addr_validate(&merchant)?;
+    let state = STATE.load(deps.storage)?;
+    Ok(MerchantFeeResponse { fee_bps: merchant_fee_bps(deps.storage, &state, &merchant)? })
+}
+
+pub fn get_balances(deps: Deps, merchant: String) -> StdResult<BalancesResponse> {
+    let merchant = deps.api.addr_validate(&merchant)?;
+    let balances: StdResult<Vec<Asset>> = BALANCES
+        .prefix(&merchant)
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, balance)| balance))
+        .collect();
+    Ok(BalancesResponse { balances: balances? })
+}
+
+pub fn get_merchant(deps: Deps, merchant: String) -> StdResult<MerchantResponse> {
+    let merchant = deps.api.addr_validate(&merchant)?;
+    match MERCHANTS.may_load(deps.storage, &merchant)? {
+        None => Err(StdError::NotFound {kind: String::from("merchant")}),
+        Some(merchant) => Ok(MerchantResponse { merchant }),
     }
 }
 
+pub fn list_merchants(deps: Deps, start_after: Option<String>, limit: Option<u32>) -> StdResult<MerchantsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+    let merchants: StdResult<Vec<Merchant>> = MERCHANTS
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, merchant)| merchant))
+        .collect();
+    Ok(MerchantsResponse { merchants: merchants? })
+}
+
+pub fn get_merchant_stats(deps: Deps, merchant: String) -> StdResult<StatsResponse> {
+    let merchant = deps.api.addr_validate(&merchant)?;
+    Ok(StatsResponse { stats: MERCHANT_STATS.may_load(deps.storage, &merchant)?.unwrap_or_default() })
+}
+
 pub fn get_payment_request_by_id(deps: Deps, id: String) -> StdResult<PaymentRequestResponse> {
-    match PAYMENT_REQUESTS.may_load(deps.storage, id.clone())? {
+    match payment_requests().may_load(deps.storage, request_key(&id)?)? {
         None => Err(StdError::NotFound {kind: String::from("payment request")}),
         Some(p) => {
             Ok(PaymentRequestResponse {
@@ -204,4 +1668,208 @@ pub fn get_payment_request_by_id(deps: Deps, id: String) -> StdResult<PaymentReq
             })
         }
     }
-}
\ No newline at end of file
+}
+
+pub fn list_by_merchant(deps: Deps, merchant: String, start_after: Option<String>, limit: Option<u32>) -> StdResult<PaymentRequestsResponse> {
+    let merchant = deps.api.addr_validate(&merchant)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = match start_after {
+        None => None,
+        Some(id) => Some(Bound::Exclusive(request_key(&id)?.wrapped)),
+    };
+    let payment_requests: StdResult<Vec<PaymentRequest>> = payment_requests()
+        .idx
+        .merchant
+        .prefix(merchant.as_bytes().to_vec())
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, p)| p))
+        .collect();
+    Ok(PaymentRequestsResponse { payment_requests: payment_requests? })
+}
+
+pub fn list_by_customer(deps: Deps, customer: String, start_after: Option<String>, limit: Option<u32>) -> StdResult<PaymentRequestsResponse> {
+    let customer = deps.api.addr_validate(&customer)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = match start_after {
+        None => None,
+        Some(id) => Some(Bound::Exclusive(request_key(&id)?.wrapped)),
+    };
+    let payment_requests: StdResult<Vec<PaymentRequest>> = payment_requests()
+        .idx
+        .customer
+        .prefix(customer.as_bytes().to_vec())
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, p)| p))
+        .collect();
+    Ok(PaymentRequestsResponse { payment_requests: payment_requests? })
+}
+
+pub fn list_by_status(deps: Deps, status: PaymentRequestStatus, start_after: Option<String>, limit: Option<u32>) -> StdResult<PaymentRequestsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = match start_after {
+        None => None,
+        Some(id) => Some(Bound::Exclusive(request_key(&id)?.wrapped)),
+    };
+    let payment_requests: StdResult<Vec<PaymentRequest>> = payment_requests()
+        .idx
+        .status
+        .prefix(status.as_str().as_bytes().to_vec())
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, p)| p))
+        .collect();
+    Ok(PaymentRequestsResponse { payment_requests: payment_requests? })
+}
+
+pub fn get_milestones(deps: Deps, id: String) -> StdResult<MilestonesResponse> {
+    let p = get_payment_request_by_id(deps, id)?.payment_request;
+    Ok(MilestonesResponse { milestones: p.milestones })
+}
+
+pub fn list_contributions(deps: Deps, id: String, start_after: Option<String>, limit: Option<u32>) -> StdResult<ContributionsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+    let contributions: StdResult<Vec<Contribution>> = CONTRIBUTIONS
+        .prefix(id.as_str())
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            let (payer, assets) = item?;
+            Ok(Contribution { payer: Addr::unchecked(String::from_utf8(payer)?), assets })
+        })
+        .collect();
+    Ok(ContributionsResponse { contributions: contributions? })
+}
+
+pub fn get_dispute(deps: Deps, id: String) -> StdResult<DisputeResponse> {
+    match DISPUTES.may_load(deps.storage, id)? {
+        None => Err(StdError::NotFound {kind: String::from("dispute")}),
+        Some(dispute) => Ok(DisputeResponse { dispute }),
+    }
+}
+
+pub fn list_open_disputes(deps: Deps, start_after: Option<String>, limit: Option<u32>) -> StdResult<DisputesResponse> {
+    let disputed = list_by_status(deps, PaymentRequestStatus::Disputed, start_after, limit)?;
+    let disputes: StdResult<Vec<Dispute>> = disputed
+        .payment_requests
+        .into_iter()
+        .map(|p| DISPUTES.load(deps.storage, p.id))
+        .collect();
+    Ok(DisputesResponse { disputes: disputes? })
+}
+
+pub fn get_plan(deps: Deps, plan_id: String) -> StdResult<PlanResponse> {
+    match PLANS.may_load(deps.storage, plan_key(&plan_id)?)? {
+        None => Err(StdError::NotFound {kind: String::from("plan")}),
+        Some(plan) => Ok(PlanResponse { plan }),
+    }
+}
+
+pub fn get_subscription(deps: Deps, subscription_id: String) -> StdResult<SubscriptionResponse> {
+    match subscriptions().may_load(deps.storage, subscription_key(&subscription_id)?)? {
+        None => Err(StdError::NotFound {kind: String::from("subscription")}),
+        Some(subscription) => Ok(SubscriptionResponse { subscription }),
+    }
+}
+
+pub fn list_subscriptions_by_merchant(deps: Deps, merchant: String, start_after: Option<String>, limit: Option<u32>) -> StdResult<SubscriptionsResponse> {
+    let merchant = deps.api.addr_validate(&merchant)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = match start_after {
+        None => None,
+        Some(id) => Some(Bound::Exclusive(subscription_key(&id)?.wrapped)),
+    };
+    let subscriptions: StdResult<Vec<Subscription>> = subscriptions()
+        .idx
+        .merchant
+        .prefix(merchant.as_bytes().to_vec())
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, s)| s))
+        .collect();
+    Ok(SubscriptionsResponse { subscriptions: subscriptions? })
+}
+
+pub fn list_subscriptions_by_subscriber(deps: Deps, subscriber: String, start_after: Option<String>, limit: Option<u32>) -> StdResult<SubscriptionsResponse> {
+    let subscriber = deps.api.addr_validate(&subscriber)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = match start_after {
+        None => None,
+        Some(id) => Some(Bound::Exclusive(subscription_key(&id)?.wrapped)),
+    };
+    let subscriptions: StdResult<Vec<Subscription>> = subscriptions()
+        .idx
+        .subscriber
+        .prefix(subscriber.as_bytes().to_vec())
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, s)| s))
+        .collect();
+    Ok(SubscriptionsResponse { subscriptions: subscriptions? })
+}
+
+pub fn get_payment_intent(deps: Deps, id: String) -> StdResult<PaymentIntentResponse> {
+    match payment_intents().may_load(deps.storage, intent_key(&id)?)? {
+        None => Err(StdError::NotFound {kind: String::from("payment_intent")}),
+        Some(payment_intent) => Ok(PaymentIntentResponse { payment_intent }),
+    }
+}
+
+pub fn list_intents_by_merchant(deps: Deps, merchant: String, start_after: Option<String>, limit: Option<u32>) -> StdResult<PaymentIntentsResponse> {
+    let merchant = deps.api.addr_validate(&merchant)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = match start_after {
+        None => None,
+        Some(id) => Some(Bound::Exclusive(intent_key(&id)?.wrapped)),
+    };
+    let payment_intents: StdResult<Vec<PaymentIntent>> = payment_intents()
+        .idx
+        .merchant
+        .prefix(merchant.as_bytes().to_vec())
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, i)| i))
+        .collect();
+    Ok(PaymentIntentsResponse { payment_intents: payment_intents? })
+}
+
+pub fn list_intents_by_customer(deps: Deps, customer: String, start_after: Option<String>, limit: Option<u32>) -> StdResult<PaymentIntentsResponse> {
+    let customer = deps.api.addr_validate(&customer)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = match start_after {
+        None => None,
+        Some(id) => Some(Bound::Exclusive(intent_key(&id)?.wrapped)),
+    };
+    let payment_intents: StdResult<Vec<PaymentIntent>> = payment_intents()
+        .idx
+        .customer
+        .prefix(customer.as_bytes().to_vec())
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, i)| i))
+        .collect();
+    Ok(PaymentIntentsResponse { payment_intents: payment_intents? })
+}
+
+pub fn get_offer(deps: Deps, id: String) -> StdResult<OfferResponse> {
+    match OFFERS.may_load(deps.storage, offer_key(&id)?)? {
+        None => Err(StdError::NotFound {kind: String::from("offer")}),
+        Some(offer) => Ok(OfferResponse { offer }),
+    }
+}
+
+pub fn list_offers(deps: Deps, start_after: Option<String>, limit: Option<u32>) -> StdResult<OffersResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = match start_after {
+        None => None,
+        Some(id) => Some(Bound::Exclusive(offer_key(&id)?.wrapped)),
+    };
+    let offers: StdResult<Vec<Offer>> = OFFERS
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, offer)| offer))
+        .collect();
+    Ok(OffersResponse { offers: offers? })
+}
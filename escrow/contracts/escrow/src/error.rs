@@ -20,6 +20,111 @@ pub enum ContractError {
     },
 
     #[error("Payment request is not yet paid")]
-    Unpaid { }
+    Unpaid { },
+
+    #[error("Fee must be at most 10000 bps: {fee_bps}")]
+    InvalidFee { fee_bps: u64 },
+
+    #[error("Merchant has no valid secp256k1 public key registered")]
+    InvalidPubkey { },
+
+    #[error("Invoice signature is invalid")]
+    InvalidSignature { },
+
+    #[error("Invoice nonce {nonce} was already used")]
+    NonceUsed { nonce: u64 },
+
+    #[error("Batch item {index} failed: {error}")]
+    BatchItem { index: u32, error: String },
+
+    #[error("Balance is lower than the withdrawn amount")]
+    InsufficientBalance { },
+
+    #[error("Confirmation window has not passed yet")]
+    ConfirmationPending { },
+
+    #[error("Payment request can only be settled by the customer")]
+    ReleaseDisabled { },
+
+    #[error("Milestones must be non-empty shares of the requested assets adding up to the total")]
+    InvalidMilestones { },
+
+    #[error("Milestone {milestone} is not pending")]
+    MilestoneNotPending { milestone: u32 },
+
+    #[error("No approved milestone to release")]
+    NoApprovedMilestones { },
+
+    #[error("Hashlock must be a 32 byte sha256 hash")]
+    InvalidHashlock { },
+
+    #[error("Preimage does not match the hashlock")]
+    InvalidPreimage { },
+
+    #[error("Hash-locked payment requests can only be released with the preimage")]
+    HashLocked { },
+
+    #[error("Offers must deposit one non-zero asset and ask for a different non-zero asset")]
+    InvalidOffer { },
+
+    #[error("Offer does not allow partial fills")]
+    PartialFillNotAllowed { },
+
+    #[error("Fill is too small to release any of the offered asset")]
+    FillTooSmall { },
+
+    #[error("Plans must charge a non-zero cw20 amount over a non-zero period")]
+    InvalidPlan { },
+
+    #[error("Allowance or balance does not cover one period")]
+    InsufficientAllowance { },
+
+    #[error("Subscription is not active")]
+    SubscriptionInactive { },
+
+    #[error("Next period cannot be collected yet")]
+    NotDue { },
+
+    #[error("Merchant is not approved by the shop")]
+    MerchantNotApproved { },
+
+    #[error("Merchant does not accept one of the requested assets")]
+    AssetNotAccepted { },
+
+    #[error("Slippage must be at most 10000 bps: {slippage_bps}")]
+    InvalidSlippage { slippage_bps: u64 },
+
+    #[error("No oracle price available for the offered asset")]
+    PriceUnavailable { },
+
+    #[error("Payment is short of the oracle quote by more than the slippage bound")]
+    SlippageExceeded { },
+
+    #[error("Cannot migrate from {contract} {version}")]
+    InvalidMigration { contract: String, version: String },
+
+    #[error("Payment request assets must be non-empty, unique and non-zero")]
+    InvalidBasket { },
+
+    #[error("Payment request is already paid")]
+    AlreadyPaid { },
+
+    #[error("Payment request is cancelled")]
+    Cancelled { },
+
+    #[error("Payment request has expired")]
+    Expired { },
+
+    #[error("Expiration must be a block height or time")]
+    InvalidExpiration { },
+
+    #[error("Payment request has not expired")]
+    NotExpired { },
+
+    #[error("Payment request is under dispute")]
+    Disputed { },
+
+    #[error("Payment request is not under dispute")]
+    NotDisputed { }
 
 }
@@ -0,0 +1,155 @@
+use asset::{Asset, AssetInfo};
+use cosmwasm_std::{to_binary, Addr, Coin, CosmosMsg, StdResult, Uint128, WasmMsg};
+use cw20::Cw20ExecuteMsg;
+
+use crate::error::ContractError;
+use crate::state::{Milestone, MilestoneShare, MilestoneStatus};
+
+pub const MAX_BPS: u64 = 10_000;
+
+/// Moves `amount` of a cw20 token from `owner` to `recipient` using the contract's allowance
+pub fn transfer_from_msg(token: &str, owner: &Addr, recipient: &Addr, amount: Uint128) -> StdResult<CosmosMsg> {
+    Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: token.to_string(),
+        funds: vec![],
+        msg: to_binary(&Cw20ExecuteMsg::TransferFrom {
+            owner: owner.to_string(),
+            recipient: recipient.to_string(),
+            amount,
+        })?,
+    }))
+}
+
+/// Builds the transfer messages for every non-zero asset in `assets`
+pub fn transfer_msgs(assets: &[Asset], recipient: &Addr) -> StdResult<Vec<CosmosMsg>> {
+    assets
+        .iter()
+        .filter(|a| !a.amount.is_zero())
+        .map(|a| a.clone().into_msg(recipient.clone()))
+        .collect()
+}
+
+pub fn coins_to_assets(funds: &[Coin]) -> Vec<Asset> {
+    funds
+        .iter()
+        .map(|c| Asset {
+            info: AssetInfo::NativeToken { denom: c.denom.clone() },
+            amount: c.amount,
+        })
+        .collect()
+}
+
+/// Native assets are returned as coins, cw20 assets are dropped
+pub fn assets_to_coins(assets: &[Asset]) -> Vec<Coin> {
+    assets
+        .iter()
+        .filter_map(|a| match &a.info {
+            AssetInfo::NativeToken { denom } => Some(Coin { denom: denom.clone(), amount: a.amount }),
+            AssetInfo::Token { .. } => None,
+        })
+        .collect()
+}
+
+pub fn amount_of(assets: &[Asset], info: &AssetInfo) -> Uint128 {
+    assets
+        .iter()
+        .find(|a| a.info.equal(info))
+        .map(|a| a.amount)
+        .unwrap_or_default()
+}
+
+/// Adds `asset` to the matching entry of `assets`, or appends it
+pub fn add_asset(assets: &mut Vec<Asset>, asset: &Asset) {
+    match assets.iter_mut().find(|a| a.info.equal(&asset.info)) {
+        Some(a) => a.amount += asset.amount,
+        None => assets.push(asset.clone()),
+    }
+}
+
+/// Same assets as `assets` with every amount set to zero
+pub fn zero_assets(assets: &[Asset]) -> Vec<Asset> {
+    assets
+        .iter()
+        .map(|a| Asset { info: a.info.clone(), amount: Uint128::zero() })
+        .collect()
+}
+
+/// A basket must be non-empty, list each asset once and request a non-zero amount of each
+pub fn validate_basket(assets: &[Asset]) -> Result<(), ContractError> {
+    if assets.is_empty() {
+        return Err(ContractError::InvalidBasket {});
+    }
+    for (i, asset) in assets.iter().enumerate() {
+        if asset.amount.is_zero() || assets[..i].iter().any(|a| a.info.equal(&asset.info)) {
+            return Err(ContractError::InvalidBasket {});
+        }
+    }
+    Ok(())
+}
+
+/// Turns milestone shares into the assets each milestone releases. Together they must
+/// cover `total` exactly; rounding dust from percentages goes to the last of them.
+pub fn resolve_milestones(total: &[Asset], shares: Vec<MilestoneShare>) -> Result<Vec<Milestone>, ContractError> {
+    let mut milestones: Vec<Milestone> = vec![];
+    let mut scheduled = zero_assets(total);
+    let mut last_bps: Option<usize> = None;
+    for (i, share) in shares.into_iter().enumerate() {
+        let assets: Vec<Asset> = match share {
+            MilestoneShare::Bps(bps) => {
+                if bps > MAX_BPS {
+                    return Err(ContractError::InvalidMilestones {});
+                }
+                last_bps = Some(i);
+                total
+                    .iter()
+                    .map(|a| Asset { info: a.info.clone(), amount: a.amount.multiply_ratio(bps, MAX_BPS) })
+                    .collect()
+            }
+            MilestoneShare::Assets(assets) => assets,
+        };
+        if assets.iter().all(|a| a.amount.is_zero()) || assets.iter().any(|a| amount_of(total, &a.info).is_zero()) {
+            return Err(ContractError::InvalidMilestones {});
+        }
+        for asset in assets.iter() {
+            add_asset(&mut scheduled, asset);
+        }
+        milestones.push(Milestone { assets, status: MilestoneStatus::Pending });
+    }
+    for asset in total {
+        let missing = asset
+            .amount
+            .checked_sub(amount_of(&scheduled, &asset.info))
+            .map_err(|_| ContractError::InvalidMilestones {})?;
+        if missing.is_zero() {
+            continue;
+        }
+        match last_bps {
+            Some(i) if missing.u128() < milestones.len() as u128 => {
+                add_asset(&mut milestones[i].assets, &Asset { info: asset.info.clone(), amount: missing })
+            }
+            _ => return Err(ContractError::InvalidMilestones {}),
+        }
+    }
+    Ok(milestones)
+}
+
+/// Splits `assets` into the part kept by the recipient and the fee of `fee_bps`
+pub fn split_fee(assets: &[Asset], fee_bps: u64) -> StdResult<(Vec<Asset>, Vec<Asset>)> {
+    let mut net: Vec<Asset> = vec![];
+    let mut fees: Vec<Asset> = vec![];
+    for asset in assets {
+        let fee = asset.amount.multiply_ratio(fee_bps, MAX_BPS);
+        net.push(Asset { info: asset.info.clone(), amount: asset.amount.checked_sub(fee)? });
+        fees.push(Asset { info: asset.info.clone(), amount: fee });
+    }
+    Ok((net, fees))
+}
+
+pub fn format_assets(assets: &[Asset]) -> String {
+    assets
+        .iter()
+        .filter(|a| !a.amount.is_zero())
+        .map(|a| a.to_string())
+        .collect::<Vec<String>>()
+        .join(",")
+}
@@ -1,8 +1,12 @@
 pub mod contract;
 mod error;
+mod helpers;
 pub mod msg;
+mod response;
 pub mod state;
 
+#[cfg(test)]
+mod test_utils;
 #[cfg(test)]
 mod tests;
 
@@ -1,40 +1,278 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use cosmwasm_std::{Addr,Uint64,Uint128, Binary};
-use cw20::Cw20ReceiveMsg;
-use asset::{Asset};
+use cosmwasm_std::{Addr, Binary, Decimal, Timestamp, Uint128};
+use cw20::{Cw20ReceiveMsg, Expiration};
+use asset::{Asset, AssetInfo};
 
-use crate::state::PaymentRequest;
+use crate::state::{AssetStats, Contribution, Dispute, Merchant, Milestone, MilestoneShare, Offer, PaymentIntent, PaymentRequest, PaymentRequestStatus, Plan, State, Stats, Subscription};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InstantiateMsg {
     pub shop: Addr,
+    pub arbiter: Option<Addr>,
+    pub fee_bps: u64,
+    /// Seconds before anyone can release a paid request, `0` to disable release
+    pub confirmation_window: u64,
+    /// Code id of the receipt contract (`contracts/receipt`) to instantiate as the receipt
+    /// collection. Stock cw721-base drops the `ReceiptMetadata` extension receipts are
+    /// minted with.
+    pub receipt_code_id: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrateMsg {}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct NewPaymentRequest {
+    pub assets: Vec<Asset>,
+    pub order_id: String,
+    pub expires: Option<Expiration>,
+}
+
+/// Payment request issued off-chain by a merchant. The merchant signs the sha256 hash
+/// of the JSON serialization of its `InvoicePayload` with the secp256k1 key registered
+/// through `RegisterPubkey`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Invoice {
+    pub merchant: String,
+    pub assets: Vec<Asset>,
+    pub order_id: String,
+    pub expires: Expiration,
+    pub nonce: u64,
+}
+
+/// What the merchant signs: the invoice bound to one escrow contract on one chain, so
+/// the signature cannot be replayed against another deployment
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct InvoicePayload {
+    pub chain_id: String,
+    pub contract: String,
+    pub invoice: Invoice,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ExecuteMsg {
-    CreatePaymentRequest { asset: Asset, order_id: String },
+    ApplyAsMerchant { name: String, payout_address: Option<String>, accepted_assets: Vec<AssetInfo> },
+    ApproveMerchant { merchant: String },
+    SuspendMerchant { merchant: String },
+    CreatePaymentRequest { assets: Vec<Asset>, order_id: String, expires: Option<Expiration> },
+    CreatePricedPaymentRequest { amount: Uint128, order_id: String, expires: Option<Expiration> },
+    CreateHtlc { assets: Vec<Asset>, order_id: String, hashlock: Binary, expires: Expiration },
+    CreatePaymentRequests { requests: Vec<NewPaymentRequest> },
+    CancelPaymentRequest { id: String },
+    SetMilestones { id: String, milestones: Vec<MilestoneShare> },
+    ApproveMilestone { id: String, milestone: u32 },
+    UpdatePaymentRequest { id: String, assets: Option<Vec<Asset>>, order_id: Option<String> },
+    RegisterPubkey { pubkey: Binary },
+    PayInvoice { invoice: Invoice, signature: Binary },
     PayIntoPaymentRequest { id: String },
     ApproveRefund { id: String, asset: Asset },
     SettlePaymentRequest { id: String },
+    SettlePaymentRequests { ids: Vec<String> },
+    Release { id: String },
+    ClaimHtlc { id: String, preimage: Binary },
+    Reclaim { id: String },
+    RaiseDispute { id: String, reason: String },
+    ResolveDispute { id: String, merchant_assets: Vec<Asset> },
+    CreatePlan { asset: Asset, period: u64 },
+    Subscribe { plan_id: String },
+    Collect { subscription_id: String },
+    CancelSubscription { subscription_id: String },
+    CreateOffer { ask: Asset, counterparty: Option<String>, partial_fills: bool },
+    FillOffer { id: String },
+    CancelOffer { id: String },
+    CreatePaymentIntent { merchant: String, description: String, expires: Expiration },
+    AcceptPaymentIntent { id: String },
+    RejectPaymentIntent { id: String },
+    ReclaimPaymentIntent { id: String },
+    UpdateConfig { admin: Option<String>, fee_bps: Option<u64>, confirmation_window: Option<u64> },
+    SetMerchantFee { merchant: String, fee_bps: Option<u64> },
+    SetPriceFeed { price_feed: Option<String>, assets: Vec<AssetInfo>, max_slippage_bps: u64 },
+    SetPullPayments { enabled: bool },
+    Withdraw { assets: Option<Vec<Asset>> },
     Receive(Cw20ReceiveMsg),
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
-    GetPaymentRequestById { id: String }
+    GetConfig {},
+    GetMerchantFee { merchant: String },
+    GetBalances { merchant: String },
+    GetMerchant { merchant: String },
+    ListMerchants { start_after: Option<String>, limit: Option<u32> },
+    GetPaymentRequestById { id: String },
+    ListByMerchant { merchant: String, start_after: Option<String>, limit: Option<u32> },
+    ListByCustomer { customer: String, start_after: Option<String>, limit: Option<u32> },
+    ListByStatus { status: PaymentRequestStatus, start_after: Option<String>, limit: Option<u32> },
+    GetMilestones { id: String },
+    ListContributions { id: String, start_after: Option<String>, limit: Option<u32> },
+    GetDispute { id: String },
+    ListOpenDisputes { start_after: Option<String>, limit: Option<u32> },
+    GetOffer { id: String },
+    ListOffers { start_after: Option<String>, limit: Option<u32> },
+    GetPaymentIntent { id: String },
+    ListIntentsByMerchant { merchant: String, start_after: Option<String>, limit: Option<u32> },
+    ListIntentsByCustomer { customer: String, start_after: Option<String>, limit: Option<u32> },
+    GetPlan { plan_id: String },
+    GetSubscription { subscription_id: String },
+    ListSubscriptionsByMerchant { merchant: String, start_after: Option<String>, limit: Option<u32> },
+    ListSubscriptionsBySubscriber { subscriber: String, start_after: Option<String>, limit: Option<u32> },
+    GetMerchantStats { merchant: String },
+    GetAssetStats { asset: AssetInfo },
+    GetTotalStats {},
 } 
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum Cw20HookMsg {
-    PayIntoPaymentRequest { id: String }
+    PayIntoPaymentRequest { id: String },
+    PayInvoice { invoice: Invoice, signature: Binary },
+    CreateOffer { ask: Asset, counterparty: Option<String>, partial_fills: bool },
+    FillOffer { id: String },
+    CreatePaymentIntent { merchant: String, description: String, expires: Expiration },
 } 
 
+/// Query interface of the price feed contract
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PriceFeedQueryMsg {
+    Price { asset: AssetInfo },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PriceResponse {
+    /// Reference units per unit of the asset
+    pub price: Decimal,
+}
+
+/// Extension of the cw721 receipt minted to the customer when a request settles
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ReceiptMetadata {
+    pub payment_request_id: String,
+    pub merchant: Addr,
+    pub order_id: String,
+    pub assets: Vec<Asset>,
+    pub settled_at: Timestamp,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct ConfigResponse {
+    pub config: State,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct MerchantFeeResponse {
+    pub fee_bps: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct BalancesResponse {
+    pub balances: Vec<Asset>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct MerchantResponse {
+    pub merchant: Merchant,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct MerchantsResponse {
+    pub merchants: Vec<Merchant>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct PaymentRequestResponse {
     pub payment_request: PaymentRequest,
-}
\ No newline at end of file
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct PaymentRequestsResponse {
+    pub payment_requests: Vec<PaymentRequest>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct MilestonesResponse {
+    pub milestones: Vec<Milestone>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct ContributionsResponse {
+    pub contributions: Vec<Contribution>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct DisputeResponse {
+    pub dispute: Dispute,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct DisputesResponse {
+    pub disputes: Vec<Dispute>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct OfferResponse {
+    pub offer: Offer,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct OffersResponse {
+    pub offers: Vec<Offer>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct PaymentIntentResponse {
+    pub payment_intent: PaymentIntent,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct PaymentIntentsResponse {
+    pub payment_intents: Vec<PaymentIntent>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct PlanResponse {
+    pub plan: Plan,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct StatsResponse {
+    pub stats: Stats,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct AssetStatsResponse {
+    pub stats: AssetStats,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct SubscriptionResponse {
+    pub subscription: Subscription,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct SubscriptionsResponse {
+    pub subscriptions: Vec<Subscription>,
+}
@@ -1,26 +1,394 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{Addr, Uint128, Uint64};
-use cw_storage_plus::{Map, Item};
-use asset::Asset;
+use cosmwasm_std::{Addr, Binary, StdError, StdResult, Timestamp, Uint128, Uint64};
+use cw_storage_plus::{Index, IndexList, IndexedMap, Item, Map, MultiIndex, U64Key};
+use cw20::Expiration;
+use asset::{Asset, AssetInfo};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentRequestStatus {
+    Unpaid,
+    PartiallyPaid,
+    Paid,
+    Disputed,
+    Cancelled,
+}
+
+impl PaymentRequestStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PaymentRequestStatus::Unpaid => "unpaid",
+            PaymentRequestStatus::PartiallyPaid => "partially_paid",
+            PaymentRequestStatus::Paid => "paid",
+            PaymentRequestStatus::Disputed => "disputed",
+            PaymentRequestStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+/// Part of a payment request's total a milestone releases
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MilestoneShare {
+    /// Percentage of every requested asset, in basis points
+    Bps(u64),
+    Assets(Vec<Asset>),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MilestoneStatus {
+    Pending,
+    Approved,
+    Released,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Milestone {
+    pub assets: Vec<Asset>,
+    pub status: MilestoneStatus,
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct PaymentRequest {
         pub id: String,
-        pub asset: Asset,
+        pub assets: Vec<Asset>,
         pub order_id: String,
-        pub paid_amount: Uint128,
+        pub paid_assets: Vec<Asset>,
         pub refund_amount: Uint128,
         pub merchant: Addr,
-        pub customer: Addr
+        pub customer: Addr,
+        pub status: PaymentRequestStatus,
+        pub expires: Option<Expiration>,
+        /// sha256 hash whose preimage releases the funds of an HTLC request
+        pub hashlock: Option<Binary>,
+        /// Stages the funds are released in; empty when they are released at once
+        pub milestones: Vec<Milestone>,
+        /// Block time the request became fully paid
+        pub paid_at: Option<Timestamp>,
+        /// Amount due in the price feed's reference unit. `assets` stays empty until the
+        /// request is paid and is then fixed from the oracle price of the paid asset.
+        pub reference_amount: Option<Uint128>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Contribution {
+    pub payer: Addr,
+    pub assets: Vec<Asset>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct State {
     pub last_id: Uint64,
     pub shop: Addr,
+    pub arbiter: Option<Addr>,
+    pub admin: Addr,
+    /// Platform fee paid to the shop on settlement, in basis points
+    pub fee_bps: u64,
+    /// Seconds after payment during which only the customer can settle. Once it passes
+    /// without a dispute anyone can release the funds to the merchant. `0` disables
+    /// release, leaving settlement to the customer.
+    pub confirmation_window: u64,
+    /// cw721 collection receipts are minted from, once instantiated
+    pub receipt_contract: Option<Addr>,
+    /// Oracle pricing assets in the reference unit of priced requests
+    pub price_feed: Option<Addr>,
+    /// Assets priced requests can be paid in
+    pub price_assets: Vec<AssetInfo>,
+    /// How far a payment may fall short of the oracle quote, in basis points
+    pub max_slippage_bps: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DisputeResolution {
+    pub resolved_by: Addr,
+    pub merchant_assets: Vec<Asset>,
+    pub customer_assets: Vec<Asset>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Dispute {
+    pub id: String,
+    pub raised_by: Addr,
+    pub reason: String,
+    pub raised_at: Timestamp,
+    pub resolution: Option<DisputeResolution>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MerchantStatus {
+    Pending,
+    Approved,
+    Suspended,
+}
+
+/// Merchant profile in the shop's registry
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Merchant {
+    pub address: Addr,
+    pub name: String,
+    /// Receives the merchant's settlements and withdrawals
+    pub payout_address: Addr,
+    /// Assets the merchant's requests may be denominated in; any asset when empty
+    pub accepted_assets: Vec<AssetInfo>,
+    pub status: MerchantStatus,
+}
+
+/// Running aggregates kept per merchant and protocol-wide
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct Stats {
+    pub requests_created: u64,
+    /// Requests that became fully paid
+    pub requests_paid: u64,
+    pub requests_settled: u64,
+    /// Requests closed with a refund to their payers
+    pub requests_refunded: u64,
+    pub paid_volume: Vec<Asset>,
+    /// Volume released to merchants, before the platform fee
+    pub settled_volume: Vec<Asset>,
+    pub refunded_volume: Vec<Asset>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct AssetStats {
+    pub paid: Uint128,
+    pub settled: Uint128,
+    pub refunded: Uint128,
+}
+
+/// Recurring charge a merchant offers to subscribers
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Plan {
+    pub id: String,
+    pub merchant: Addr,
+    /// cw20 token and amount collected every period
+    pub asset: Asset,
+    /// Billing period in seconds
+    pub period: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionStatus {
+    Active,
+    Lapsed,
+    Cancelled,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Subscription {
+    pub id: String,
+    pub plan_id: String,
+    pub merchant: Addr,
+    pub subscriber: Addr,
+    pub status: SubscriptionStatus,
+    /// Earliest time the next period can be collected
+    pub next_collection: Timestamp,
+    /// Consecutive periods that could not be collected
+    pub missed_payments: u32,
+}
+
+/// Two-sided swap: the maker deposits `offer` and asks for `ask` in exchange
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Offer {
+    pub id: String,
+    pub maker: Addr,
+    pub offer: Asset,
+    pub ask: Asset,
+    /// Only this address may fill the offer when set
+    pub counterparty: Option<Addr>,
+    pub partial_fills: bool,
+    /// Part of `ask` paid by takers so far
+    pub filled: Uint128,
+}
+
+impl Offer {
+    /// Part of `offer` already released to takers
+    pub fn released(&self) -> Uint128 {
+        self.offer.amount.multiply_ratio(self.filled, self.ask.amount)
+    }
+}
+
+/// Funds a customer locks for a merchant before the merchant has quoted. Accepting it
+/// turns it into a paid payment request.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PaymentIntent {
+    pub id: String,
+    pub customer: Addr,
+    pub merchant: Addr,
+    pub description: String,
+    pub assets: Vec<Asset>,
+    /// After this the merchant can no longer accept and the funds can be reclaimed
+    pub expires: Expiration,
+}
+
+pub struct PaymentRequestIndexes<'a> {
+    // Second element of each index key is the primary key
+    pub merchant: MultiIndex<'a, (Vec<u8>, Vec<u8>), PaymentRequest>,
+    pub customer: MultiIndex<'a, (Vec<u8>, Vec<u8>), PaymentRequest>,
+    pub status: MultiIndex<'a, (Vec<u8>, Vec<u8>), PaymentRequest>,
+}
+
+impl<'a> IndexList<PaymentRequest> for PaymentRequestIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<PaymentRequest>> + '_> {
+        let v: Vec<&dyn Index<PaymentRequest>> = vec![&self.merchant, &self.customer, &self.status];
+        Box::new(v.into_iter())
+    }
+}
+
+/// Ids are counters keyed as integers, so ranges list records in creation order
+fn numeric_key(id: &str, kind: &str) -> StdResult<U64Key> {
+    id.parse::<u64>()
+        .map(U64Key::new)
+        .map_err(|_| StdError::not_found(kind))
+}
+
+pub fn request_key(id: &str) -> StdResult<U64Key> {
+    numeric_key(id, "payment_request")
+}
+
+pub fn offer_key(id: &str) -> StdResult<U64Key> {
+    numeric_key(id, "offer")
+}
+
+pub fn plan_key(id: &str) -> StdResult<U64Key> {
+    numeric_key(id, "plan")
+}
+
+pub fn subscription_key(id: &str) -> StdResult<U64Key> {
+    numeric_key(id, "subscription")
+}
+
+pub fn intent_key(id: &str) -> StdResult<U64Key> {
+    numeric_key(id, "payment_intent")
+}
+
+pub fn payment_requests<'a>() -> IndexedMap<'a, U64Key, PaymentRequest, PaymentRequestIndexes<'a>> {
+    let indexes = PaymentRequestIndexes {
+        merchant: MultiIndex::new(
+            |p, k| (p.merchant.as_bytes().to_vec(), k),
+            "payment_requests",
+            "payment_requests__merchant",
+        ),
+        customer: MultiIndex::new(
+            |p, k| (p.customer.as_bytes().to_vec(), k),
+            "payment_requests",
+            "payment_requests__customer",
+        ),
+        status: MultiIndex::new(
+            |p, k| (p.status.as_str().as_bytes().to_vec(), k),
+            "payment_requests",
+            "payment_requests__status",
+        ),
+    };
+    IndexedMap::new("payment_requests", indexes)
+}
+
+pub struct SubscriptionIndexes<'a> {
+    pub merchant: MultiIndex<'a, (Vec<u8>, Vec<u8>), Subscription>,
+    pub subscriber: MultiIndex<'a, (Vec<u8>, Vec<u8>), Subscription>,
+}
+
+impl<'a> IndexList<Subscription> for SubscriptionIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Subscription>> + '_> {
+        let v: Vec<&dyn Index<Subscription>> = vec![&self.merchant, &self.subscriber];
+        Box::new(v.into_iter())
+    }
+}
+
+pub fn subscriptions<'a>() -> IndexedMap<'a, U64Key, Subscription, SubscriptionIndexes<'a>> {
+    let indexes = SubscriptionIndexes {
+        merchant: MultiIndex::new(
+            |s, k| (s.merchant.as_bytes().to_vec(), k),
+            "subscriptions",
+            "subscriptions__merchant",
+        ),
+        subscriber: MultiIndex::new(
+            |s, k| (s.subscriber.as_bytes().to_vec(), k),
+            "subscriptions",
+            "subscriptions__subscriber",
+        ),
+    };
+    IndexedMap::new("subscriptions", indexes)
+}
+
+/// Payment request as stored by the first release, which priced a request in a single
+/// asset and kept no status
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LegacyPaymentRequest {
+    pub id: String,
+    pub asset: Asset,
+    pub order_id: String,
+    pub paid_amount: Uint128,
+    pub refund_amount: Uint128,
+    pub merchant: Addr,
+    pub customer: Addr,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LegacyState {
+    pub last_id: Uint64,
+    pub shop: Addr,
+}
+
+/// First release layouts, only read by `migrate`
+pub const LEGACY_PAYMENT_REQUESTS: Map<String, LegacyPaymentRequest> = Map::new("payment_requests");
+pub const LEGACY_STATE: Item<LegacyState> = Item::new("state");
+
+pub struct PaymentIntentIndexes<'a> {
+    pub merchant: MultiIndex<'a, (Vec<u8>, Vec<u8>), PaymentIntent>,
+    pub customer: MultiIndex<'a, (Vec<u8>, Vec<u8>), PaymentIntent>,
+}
+
+impl<'a> IndexList<PaymentIntent> for PaymentIntentIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<PaymentIntent>> + '_> {
+        let v: Vec<&dyn Index<PaymentIntent>> = vec![&self.merchant, &self.customer];
+        Box::new(v.into_iter())
+    }
+}
+
+pub fn payment_intents<'a>() -> IndexedMap<'a, U64Key, PaymentIntent, PaymentIntentIndexes<'a>> {
+    let indexes = PaymentIntentIndexes {
+        merchant: MultiIndex::new(
+            |i, k| (i.merchant.as_bytes().to_vec(), k),
+            "payment_intents",
+            "payment_intents__merchant",
+        ),
+        customer: MultiIndex::new(
+            |i, k| (i.customer.as_bytes().to_vec(), k),
+            "payment_intents",
+            "payment_intents__customer",
+        ),
+    };
+    IndexedMap::new("payment_intents", indexes)
 }
 
-pub const PAYMENT_REQUESTS: Map<String, PaymentRequest> = Map::new("payment_requests");
+/// Assets paid into a request by each payer, keyed by (request id, payer)
+pub const CONTRIBUTIONS: Map<(&str, &Addr), Vec<Asset>> = Map::new("contributions");
+/// Merchant registry managed by the shop
+pub const MERCHANTS: Map<&Addr, Merchant> = Map::new("merchants");
+/// Per-merchant overrides of the platform fee, in basis points
+pub const MERCHANT_FEES: Map<&Addr, u64> = Map::new("merchant_fees");
+/// Merchants that collect settlements into their balance instead of receiving transfers
+pub const PULL_PAYMENTS: Map<&Addr, bool> = Map::new("pull_payments");
+/// Settled funds waiting to be withdrawn, keyed by (merchant, asset denom or token address)
+pub const BALANCES: Map<(&Addr, &str), Asset> = Map::new("balances");
+/// secp256k1 public keys merchants sign off-chain invoices with
+pub const MERCHANT_PUBKEYS: Map<&Addr, Binary> = Map::new("merchant_pubkeys");
+/// Invoice nonces already used, keyed by (merchant, nonce)
+pub const USED_NONCES: Map<(&Addr, U64Key), bool> = Map::new("used_nonces");
+pub const MERCHANT_STATS: Map<&Addr, Stats> = Map::new("merchant_stats");
+/// Volume aggregates keyed by asset denom or token address
+pub const ASSET_STATS: Map<&str, AssetStats> = Map::new("asset_stats");
+pub const TOTAL_STATS: Item<Stats> = Item::new("total_stats");
+pub const DISPUTES: Map<String, Dispute> = Map::new("disputes");
 pub const STATE: Item<State> = Item::new("state");
+pub const PLANS: Map<U64Key, Plan> = Map::new("plans");
+pub const LAST_PLAN_ID: Item<Uint64> = Item::new("last_plan_id");
+pub const LAST_SUBSCRIPTION_ID: Item<Uint64> = Item::new("last_subscription_id");
+pub const OFFERS: Map<U64Key, Offer> = Map::new("offers");
+pub const LAST_OFFER_ID: Item<Uint64> = Item::new("last_offer_id");
+pub const LAST_INTENT_ID: Item<Uint64> = Item::new("last_intent_id");
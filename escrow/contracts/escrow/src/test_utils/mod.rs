@@ -0,0 +1,7 @@
+mod helpers;
+mod price_querier;
+mod token_querier;
+
+pub use helpers::mock_dependencies;
+pub use price_querier::PriceQuerier;
+pub use token_querier::TokenQuerier;
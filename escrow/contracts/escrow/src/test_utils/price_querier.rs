@@ -0,0 +1,26 @@
+use cosmwasm_std::{to_binary, ContractResult, Decimal, QuerierResult, SystemResult};
+use asset::AssetInfo;
+use std::collections::HashMap;
+
+use crate::msg::{PriceFeedQueryMsg, PriceResponse};
+
+/// Answers price feed queries, keyed by asset
+#[derive(Default)]
+pub struct PriceQuerier {
+    prices: HashMap<String, Decimal>,
+}
+
+impl PriceQuerier {
+    pub fn handle_query(&self, query: PriceFeedQueryMsg) -> QuerierResult {
+        match query {
+            PriceFeedQueryMsg::Price { asset } => match self.prices.get(&asset.to_string()) {
+                Some(price) => SystemResult::Ok(ContractResult::from(to_binary(&PriceResponse { price: *price }))),
+                None => SystemResult::Ok(ContractResult::Err(format!("no price for {}", asset))),
+            },
+        }
+    }
+
+    pub fn set_price(&mut self, asset: &AssetInfo, price: Decimal) {
+        self.prices.insert(asset.to_string(), price);
+    }
+}
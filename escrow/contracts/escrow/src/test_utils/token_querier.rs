@@ -0,0 +1,39 @@
+use cosmwasm_std::{to_binary, ContractResult, QuerierResult, SystemError, SystemResult, Uint128};
+use cw20::{AllowanceResponse, BalanceResponse, Cw20QueryMsg, Expiration};
+use std::collections::HashMap;
+
+/// Answers cw20 balance and allowance queries, keyed by (token, owner)
+#[derive(Default)]
+pub struct TokenQuerier {
+    balances: HashMap<(String, String), Uint128>,
+    allowances: HashMap<(String, String), Uint128>,
+}
+
+impl TokenQuerier {
+    pub fn handle_query(&self, token: &str, query: Cw20QueryMsg) -> QuerierResult {
+        match query {
+            Cw20QueryMsg::Balance { address } => {
+                let balance = self.balances.get(&(token.to_string(), address)).cloned().unwrap_or_default();
+                SystemResult::Ok(ContractResult::from(to_binary(&BalanceResponse { balance })))
+            }
+            Cw20QueryMsg::Allowance { owner, .. } => {
+                let allowance = self.allowances.get(&(token.to_string(), owner)).cloned().unwrap_or_default();
+                SystemResult::Ok(ContractResult::from(to_binary(&AllowanceResponse {
+                    allowance,
+                    expires: Expiration::Never {},
+                })))
+            }
+            _ => SystemResult::Err(SystemError::UnsupportedRequest {
+                kind: String::from("unknown cw20 query"),
+            }),
+        }
+    }
+
+    pub fn set_balance(&mut self, token: &str, owner: &str, amount: u128) {
+        self.balances.insert((token.to_string(), owner.to_string()), Uint128::from(amount));
+    }
+
+    pub fn set_allowance(&mut self, token: &str, owner: &str, amount: u128) {
+        self.allowances.insert((token.to_string(), owner.to_string()), Uint128::from(amount));
+    }
+}
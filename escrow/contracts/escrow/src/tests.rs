@@ -1,11 +1,112 @@
 use super::contract::*;
 use super::msg::*;
 use super::error::*;
-use super::state::{STATE, PAYMENT_REQUESTS};
+use super::state::{STATE, LEGACY_PAYMENT_REQUESTS, LEGACY_STATE, LegacyPaymentRequest, LegacyState, AssetStats, MerchantStatus, MilestoneShare, MilestoneStatus, PaymentRequestStatus, Stats, SubscriptionStatus, payment_requests, request_key};
+use super::test_utils;
+use super::response::MsgInstantiateContractResponse;
 
-use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
-use cosmwasm_std::{coins, from_binary, Addr, Uint64, Uint128};
+use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info, MOCK_CONTRACT_ADDR};
+use cosmwasm_std::{coin, coins, from_binary, to_binary, Addr, Binary, BankMsg, ContractResult, CosmosMsg, Decimal, DepsMut, Env, Event, Reply, ReplyOn, StdResult, SubMsgExecutionResponse, Uint64, Uint128, WasmMsg};
+use cw2::{get_contract_version, set_contract_version};
+use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg, Expiration};
 use asset::{ Asset, AssetInfo };
+use sha2::{Digest, Sha256};
+use cw721_base::{ExecuteMsg as Cw721ExecuteMsg, InstantiateMsg as Cw721InstantiateMsg, MintMsg};
+use protobuf::Message;
+use k256::ecdsa::{signature::Signer, Signature, SigningKey};
+
+fn init_contract(mut deps: DepsMut, env: Env) {
+    let msg = InstantiateMsg {
+        shop: Addr::unchecked("shop"),
+        arbiter: Some(Addr::unchecked("arbiter")),
+        fee_bps: 0,
+        confirmation_window: 0,
+        receipt_code_id: None,
+    };
+    let info = mock_info("creator", &[]);
+    instantiate(deps.branch(), env, info, msg).unwrap();
+    for merchant in ["host", "merchant", "other_merchant"] {
+        approve_merchant(deps.branch(), merchant);
+    }
+}
+
+fn approve_merchant(mut deps: DepsMut, merchant: &str) {
+    execute(
+        deps.branch(),
+        mock_env(),
+        mock_info(merchant, &[]),
+        ExecuteMsg::ApplyAsMerchant { name: String::from(merchant), payout_address: None, accepted_assets: vec![] },
+    ).unwrap();
+    execute(
+        deps,
+        mock_env(),
+        mock_info("shop", &[]),
+        ExecuteMsg::ApproveMerchant { merchant: String::from(merchant) },
+    ).unwrap();
+}
+
+fn uluna(amount: u128) -> Asset {
+    Asset {
+        info: AssetInfo::NativeToken{ denom: String::from("uluna") },
+        amount: Uint128::from(amount),
+    }
+}
+
+fn create_request(deps: DepsMut, env: Env, merchant: &str, amount: u128, denom: &str) {
+    let asset: Asset = Asset {
+        info: AssetInfo::NativeToken{ denom: String::from(denom) },
+        amount: Uint128::from(amount),
+    };
+    execute(
+        deps,
+        env,
+        mock_info(merchant, &[]),
+        ExecuteMsg::CreatePaymentRequest {assets: vec![asset], order_id: String::from("1"), expires: None}
+    ).unwrap();
+}
+
+fn pay_request(deps: DepsMut, env: Env, customer: &str, id: &str, amount: u128, denom: &str) {
+    execute(
+        deps,
+        env,
+        mock_info(customer, &coins(amount, denom)),
+        ExecuteMsg::PayIntoPaymentRequest {id: String::from(id)}
+    ).unwrap();
+}
+
+fn merchant_key() -> SigningKey {
+    SigningKey::from_bytes(&[7u8; 32]).unwrap()
+}
+
+fn invoice(nonce: u64) -> Invoice {
+    Invoice {
+        merchant: String::from("merchant"),
+        assets: vec![uluna(1000000)],
+        order_id: String::from("inv-1"),
+        expires: Expiration::AtHeight(mock_env().block.height + 100),
+        nonce,
+    }
+}
+
+fn sign_invoice(key: &SigningKey, invoice: &Invoice) -> Binary {
+    sign_invoice_for(key, invoice, &mock_env())
+}
+
+fn sign_invoice_for(key: &SigningKey, invoice: &Invoice, env: &Env) -> Binary {
+    let payload = InvoicePayload {
+        chain_id: env.block.chain_id.clone(),
+        contract: env.contract.address.to_string(),
+        invoice: invoice.clone(),
+    };
+    // The signer hashes the payload with sha256, as the contract does
+    let signature: Signature = key.sign(to_binary(&payload).unwrap().as_slice());
+    Binary::from(signature.as_ref())
+}
+
+fn register_merchant_key(deps: DepsMut) {
+    let pubkey = Binary::from(merchant_key().verifying_key().to_bytes().as_slice());
+    execute(deps, mock_env(), mock_info("merchant", &[]), ExecuteMsg::RegisterPubkey { pubkey }).unwrap();
+}
 
 #[test]
 fn proper_initialization() {
@@ -13,6 +114,10 @@ fn proper_initialization() {
 
     let msg = InstantiateMsg {
         shop: Addr::unchecked("shop"),
+        arbiter: None,
+        fee_bps: 0,
+        confirmation_window: 0,
+        receipt_code_id: None,
     };
     let info = mock_info("creator", &coins(1000, "earth"));
 
@@ -23,7 +128,7 @@ fn proper_initialization() {
     // it worked, let's query the state
     let state = STATE.load(&deps.storage).unwrap();
     assert_eq!(state.shop, "shop");
-    assert_eq!(state.last_id, Uint64::from(0 as u64));
+    assert_eq!(state.last_id, Uint64::zero());
     // let res = query(deps.as_ref(), mock_env(), QueryMsg:: {host: Addr::unchecked("creator")}).unwrap();
 
     // let value: GameStatesResponse = from_binary(&res).unwrap();
@@ -36,24 +141,29 @@ fn create_payment_request() {
 
     let msg = InstantiateMsg {
         shop: Addr::unchecked("shop"),
+        arbiter: None,
+        fee_bps: 0,
+        confirmation_window: 0,
+        receipt_code_id: None,
     };
     let info = mock_info("creator", &coins(1000, "earth"));
 
     // we can just call .unwrap() to assert this was a success
     let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+    approve_merchant(deps.as_mut(), "host");
     let auth_info = mock_info("host", &coins(1000, "paper"));
     let asset: Asset = Asset {
         info: AssetInfo::NativeToken{ denom: String::from("uluna") },
-        amount: Uint128::from(1_000_000 as u128),
+        amount: Uint128::from(1_000_000u128),
     };
     let _res = execute(
         deps.as_mut(), 
         mock_env(), 
         auth_info,
-        ExecuteMsg::CreatePaymentRequest {asset: asset, order_id: String::from("1")}
+        ExecuteMsg::CreatePaymentRequest {assets: vec![asset], order_id: String::from("1"), expires: None}
     ).unwrap();
 
-    assert_eq!(PAYMENT_REQUESTS.has(&deps.storage, String::from("1")), true);
+    assert!(payment_requests().may_load(&deps.storage, request_key("1").unwrap()).unwrap().is_some());
 }
 
 #[test]
@@ -62,24 +172,29 @@ fn pay_into_payment_request() {
 
     let msg = InstantiateMsg {
         shop: Addr::unchecked("shop"),
+        arbiter: None,
+        fee_bps: 0,
+        confirmation_window: 0,
+        receipt_code_id: None,
     };
     let info = mock_info("creator", &coins(1000, "earth"));
 
     // we can just call .unwrap() to assert this was a success
     let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+    approve_merchant(deps.as_mut(), "host");
     let auth_info = mock_info("host", &coins(1000, "paper"));
     let asset: Asset = Asset {
         info: AssetInfo::NativeToken{ denom: String::from("uluna") },
-        amount: Uint128::from(1_000_000 as u128),
+        amount: Uint128::from(1_000_000u128),
     };
     let _res = execute(
         deps.as_mut(), 
         mock_env(), 
         auth_info.clone(),
-        ExecuteMsg::CreatePaymentRequest {asset: asset, order_id: String::from("1")}
+        ExecuteMsg::CreatePaymentRequest {assets: vec![asset], order_id: String::from("1"), expires: None}
     ).unwrap();
 
-    assert_eq!(PAYMENT_REQUESTS.has(&deps.storage, String::from("1")), true);
+    assert!(payment_requests().may_load(&deps.storage, request_key("1").unwrap()).unwrap().is_some());
 
     let pay_info = mock_info("customer", &coins(1_000_000, "uluna"));
     let _res = execute(
@@ -89,46 +204,64 @@ fn pay_into_payment_request() {
         ExecuteMsg::PayIntoPaymentRequest {id: String::from("1")}
     );
 
-    let pr = PAYMENT_REQUESTS.load(&deps.storage, String::from("1")).unwrap();
+    let pr = payment_requests().load(&deps.storage, request_key("1").unwrap()).unwrap();
     assert_eq!(pr.customer, "customer");
-    assert_eq!(pr.paid_amount, Uint128::from(1_000_000 as u128));
+    assert_eq!(pr.paid_assets[0].amount, Uint128::from(1_000_000u128));
 }
 
 #[test]
-fn pay_into_payment_request_with_wrong_amount() {
+fn pay_into_payment_request_partially() {
     let mut deps = mock_dependencies(&coins(2, "token"));
 
     let msg = InstantiateMsg {
         shop: Addr::unchecked("shop"),
+        arbiter: None,
+        fee_bps: 0,
+        confirmation_window: 0,
+        receipt_code_id: None,
     };
     let info = mock_info("creator", &coins(1000, "earth"));
 
     // we can just call .unwrap() to assert this was a success
     let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+    approve_merchant(deps.as_mut(), "host");
     let auth_info = mock_info("host", &coins(1000, "paper"));
     let asset: Asset = Asset {
         info: AssetInfo::NativeToken{ denom: String::from("uluna") },
-        amount: Uint128::from(1_000_000 as u128),
+        amount: Uint128::from(1_000_000u128),
     };
     let _res = execute(
         deps.as_mut(), 
         mock_env(), 
         auth_info.clone(),
-        ExecuteMsg::CreatePaymentRequest {asset: asset, order_id: String::from("1")}
+        ExecuteMsg::CreatePaymentRequest {assets: vec![asset], order_id: String::from("1"), expires: None}
     ).unwrap();
 
-    assert_eq!(PAYMENT_REQUESTS.has(&deps.storage, String::from("1")), true);
+    assert!(payment_requests().may_load(&deps.storage, request_key("1").unwrap()).unwrap().is_some());
 
-    let pay_info = mock_info("customer", &coins(1_000_00, "uluna"));
+    let pay_info = mock_info("customer", &coins(100_000, "uluna"));
     let res = execute(
         deps.as_mut(),
         mock_env(),
         pay_info,
         ExecuteMsg::PayIntoPaymentRequest {id: String::from("1")}
+    ).unwrap();
+    assert_eq!(res.messages.len(), 0);
+
+    let pr = payment_requests().load(&deps.storage, request_key("1").unwrap()).unwrap();
+    assert_eq!(pr.paid_assets[0].amount, Uint128::from(100_000u128));
+    assert_eq!(pr.status, PaymentRequestStatus::PartiallyPaid);
+
+    // a partially paid request cannot be settled
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("customer", &[]),
+        ExecuteMsg::SettlePaymentRequest {id: String::from("1")}
     ).unwrap_err();
     match res {
-        ContractError::WrongAmount { amount: _ } => {},
-        _ => panic!("Must return wrong amount error"),
+        ContractError::Unpaid {} => {},
+        _ => panic!("Must return unpaid error"),
     }
 }
 
@@ -138,24 +271,29 @@ fn pay_into_payment_request_with_wrong_token() {
 
     let msg = InstantiateMsg {
         shop: Addr::unchecked("shop"),
+        arbiter: None,
+        fee_bps: 0,
+        confirmation_window: 0,
+        receipt_code_id: None,
     };
     let info = mock_info("creator", &coins(1000, "earth"));
 
     // we can just call .unwrap() to assert this was a success
     let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+    approve_merchant(deps.as_mut(), "host");
     let auth_info = mock_info("host", &coins(1000, "paper"));
     let asset: Asset = Asset {
         info: AssetInfo::NativeToken{ denom: String::from("uluna") },
-        amount: Uint128::from(1_000_000 as u128),
+        amount: Uint128::from(1_000_000u128),
     };
     let _res = execute(
         deps.as_mut(), 
         mock_env(), 
         auth_info.clone(),
-        ExecuteMsg::CreatePaymentRequest {asset: asset, order_id: String::from("1")}
+        ExecuteMsg::CreatePaymentRequest {assets: vec![asset], order_id: String::from("1"), expires: None}
     ).unwrap();
 
-    assert_eq!(PAYMENT_REQUESTS.has(&deps.storage, String::from("1")), true);
+    assert!(payment_requests().may_load(&deps.storage, request_key("1").unwrap()).unwrap().is_some());
 
     let pay_info = mock_info("customer", &coins(1_000_000, "paper"));
     let res = execute(
@@ -170,6 +308,2298 @@ fn pay_into_payment_request_with_wrong_token() {
     }
 }
 
+#[test]
+fn pay_into_payment_request_refunds_excess_and_other_denoms() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+    create_request(deps.as_mut(), mock_env(), "host", 1_000_000, "uluna");
+
+    let pay_info = mock_info("customer", &[coin(1_500_000, "uluna"), coin(20, "uusd")]);
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        pay_info,
+        ExecuteMsg::PayIntoPaymentRequest {id: String::from("1")}
+    ).unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: String::from("customer"),
+        amount: vec![coin(500_000, "uluna"), coin(20, "uusd")],
+    }));
+
+    let pr = payment_requests().load(&deps.storage, request_key("1").unwrap()).unwrap();
+    assert_eq!(pr.paid_assets[0].amount, Uint128::from(1_000_000u128));
+    assert_eq!(pr.status, PaymentRequestStatus::Paid);
+}
+
+#[test]
+fn pay_into_payment_request_with_cw20_refunds_excess() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+    let asset: Asset = Asset {
+        info: AssetInfo::Token{ contract_addr: String::from("token") },
+        amount: Uint128::from(1_000u128),
+    };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("host", &[]),
+        ExecuteMsg::CreatePaymentRequest {assets: vec![asset], order_id: String::from("1"), expires: None}
+    ).unwrap();
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("token", &[]),
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: String::from("customer"),
+            amount: Uint128::from(1_200u128),
+            msg: to_binary(&Cw20HookMsg::PayIntoPaymentRequest {id: String::from("1")}).unwrap(),
+        })
+    ).unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(res.messages[0].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: String::from("token"),
+        funds: vec![],
+        msg: to_binary(&Cw20ExecuteMsg::Transfer {
+            recipient: String::from("customer"),
+            amount: Uint128::from(200u128),
+        }).unwrap(),
+    }));
+
+    let pr = payment_requests().load(&deps.storage, request_key("1").unwrap()).unwrap();
+    assert_eq!(pr.customer, "customer");
+    assert_eq!(pr.paid_assets[0].amount, Uint128::from(1_000u128));
+}
+
+#[test]
+fn installments_from_several_payers() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+    let env = mock_env();
+    let asset: Asset = Asset {
+        info: AssetInfo::NativeToken{ denom: String::from("uluna") },
+        amount: Uint128::from(1_000_000u128),
+    };
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("host", &[]),
+        ExecuteMsg::CreatePaymentRequest {
+            assets: vec![asset],
+            order_id: String::from("1"),
+            expires: Some(Expiration::AtHeight(env.block.height + 10)),
+        }
+    ).unwrap();
+    pay_request(deps.as_mut(), env.clone(), "customer", "1", 300_000, "uluna");
+    pay_request(deps.as_mut(), env.clone(), "friend", "1", 200_000, "uluna");
+    pay_request(deps.as_mut(), env.clone(), "customer", "1", 100_000, "uluna");
+
+    // the last payer overpays, only the outstanding amount is accepted
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("friend", &coins(500_000, "uluna")),
+        ExecuteMsg::PayIntoPaymentRequest {id: String::from("1")}
+    ).unwrap();
+    assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: String::from("friend"),
+        amount: coins(100_000, "uluna"),
+    }));
+
+    let pr = payment_requests().load(&deps.storage, request_key("1").unwrap()).unwrap();
+    assert_eq!(pr.customer, "customer");
+    assert_eq!(pr.status, PaymentRequestStatus::Paid);
+
+    let res = execute(
+        deps.as_mut(),
+        env,
+        mock_info("friend", &coins(1, "uluna")),
+        ExecuteMsg::PayIntoPaymentRequest {id: String::from("1")}
+    ).unwrap_err();
+    match res {
+        ContractError::AlreadyPaid {} => {},
+        _ => panic!("Must return already paid error"),
+    }
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::ListContributions {
+        id: String::from("1"),
+        start_after: None,
+        limit: None,
+    }).unwrap();
+    let value: ContributionsResponse = from_binary(&res).unwrap();
+    assert_eq!(value.contributions.len(), 2);
+    assert_eq!(value.contributions[0].payer, "customer");
+    assert_eq!(value.contributions[0].assets[0].amount, Uint128::from(400_000u128));
+    assert_eq!(value.contributions[1].payer, "friend");
+    assert_eq!(value.contributions[1].assets[0].amount, Uint128::from(600_000u128));
+
+    // after expiry every payer gets their own contribution back
+    let mut expired_env = mock_env();
+    expired_env.block.height += 10;
+    let res = execute(
+        deps.as_mut(),
+        expired_env,
+        mock_info("friend", &[]),
+        ExecuteMsg::Reclaim {id: String::from("1")}
+    ).unwrap();
+    assert_eq!(res.messages.len(), 2);
+    assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: String::from("customer"),
+        amount: coins(400_000, "uluna"),
+    }));
+    assert_eq!(res.messages[1].msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: String::from("friend"),
+        amount: coins(600_000, "uluna"),
+    }));
+}
+
+#[test]
+fn basket_payment_request() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+    let loyalty = Asset {
+        info: AssetInfo::Token{ contract_addr: String::from("loyalty") },
+        amount: Uint128::from(50u128),
+    };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("host", &[]),
+        ExecuteMsg::CreatePaymentRequest {assets: vec![uluna(1_000), loyalty], order_id: String::from("1"), expires: None}
+    ).unwrap();
+
+    // the native part alone does not pay the request
+    pay_request(deps.as_mut(), mock_env(), "customer", "1", 1_000, "uluna");
+    let pr = payment_requests().load(&deps.storage, request_key("1").unwrap()).unwrap();
+    assert_eq!(pr.status, PaymentRequestStatus::PartiallyPaid);
+
+    // the cw20 part is paid over two hooks
+    for amount in [30u128, 20u128] {
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("loyalty", &[]),
+            ExecuteMsg::Receive(Cw20ReceiveMsg {
+                sender: String::from("customer"),
+                amount: Uint128::from(amount),
+                msg: to_binary(&Cw20HookMsg::PayIntoPaymentRequest {id: String::from("1")}).unwrap(),
+            })
+        ).unwrap();
+    }
+    let pr = payment_requests().load(&deps.storage, request_key("1").unwrap()).unwrap();
+    assert_eq!(pr.status, PaymentRequestStatus::Paid);
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("customer", &[]),
+        ExecuteMsg::SettlePaymentRequest {id: String::from("1")}
+    ).unwrap();
+    assert_eq!(res.messages.len(), 2);
+    assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: String::from("host"),
+        amount: coins(1_000, "uluna"),
+    }));
+    assert_eq!(res.messages[1].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: String::from("loyalty"),
+        funds: vec![],
+        msg: to_binary(&Cw20ExecuteMsg::Transfer {
+            recipient: String::from("host"),
+            amount: Uint128::from(50u128),
+        }).unwrap(),
+    }));
+}
+
+#[test]
+fn create_payment_request_with_invalid_basket() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+    for assets in [vec![], vec![uluna(1), uluna(2)], vec![uluna(0)]] {
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("host", &[]),
+            ExecuteMsg::CreatePaymentRequest {assets, order_id: String::from("1"), expires: None}
+        ).unwrap_err();
+        match res {
+            ContractError::InvalidBasket {} => {},
+            _ => panic!("Must return invalid basket error"),
+        }
+    }
+}
+
+#[test]
+fn platform_fee_on_settlement() {
+    let mut deps = mock_dependencies(&[]);
+    let msg = InstantiateMsg {
+        shop: Addr::unchecked("shop"),
+        arbiter: None,
+        fee_bps: 250,
+        confirmation_window: 0,
+        receipt_code_id: None,
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info("admin", &[]), msg).unwrap();
+    approve_merchant(deps.as_mut(), "host");
+    create_request(deps.as_mut(), mock_env(), "host", 1_000_000, "uluna");
+    pay_request(deps.as_mut(), mock_env(), "customer", "1", 1_000_000, "uluna");
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("customer", &[]),
+        ExecuteMsg::SettlePaymentRequest {id: String::from("1")}
+    ).unwrap();
+    assert_eq!(res.messages.len(), 2);
+    assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: String::from("host"),
+        amount: coins(975_000, "uluna"),
+    }));
+    assert_eq!(res.messages[1].msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: String::from("shop"),
+        amount: coins(25_000, "uluna"),
+    }));
+    assert_eq!(res.attributes[0].key, "fee_bps");
+    assert_eq!(res.attributes[0].value, "250");
+    assert_eq!(res.attributes[1].key, "fee");
+    assert_eq!(res.attributes[1].value, "25000uluna");
+
+    // only the admin can override a merchant's fee
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("host", &[]),
+        ExecuteMsg::SetMerchantFee {merchant: String::from("host"), fee_bps: Some(0)}
+    ).unwrap_err();
+    match res {
+        ContractError::Unauthorized {} => {},
+        _ => panic!("Must return unauthorized error"),
+    }
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("admin", &[]),
+        ExecuteMsg::SetMerchantFee {merchant: String::from("host"), fee_bps: Some(10_001)}
+    ).unwrap_err();
+    match res {
+        ContractError::InvalidFee { fee_bps: 10_001 } => {},
+        _ => panic!("Must return invalid fee error"),
+    }
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("admin", &[]),
+        ExecuteMsg::SetMerchantFee {merchant: String::from("host"), fee_bps: Some(100)}
+    ).unwrap();
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::GetMerchantFee {merchant: String::from("host")}).unwrap();
+    let value: MerchantFeeResponse = from_binary(&res).unwrap();
+    assert_eq!(value.fee_bps, 100);
+
+    create_request(deps.as_mut(), mock_env(), "host", 1_000_000, "uluna");
+    pay_request(deps.as_mut(), mock_env(), "customer", "2", 1_000_000, "uluna");
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("customer", &[]),
+        ExecuteMsg::SettlePaymentRequest {id: String::from("2")}
+    ).unwrap();
+    assert_eq!(res.messages[1].msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: String::from("shop"),
+        amount: coins(10_000, "uluna"),
+    }));
+}
+
+#[test]
+fn update_config() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::UpdateConfig {admin: None, fee_bps: Some(100), confirmation_window: None}
+    ).unwrap_err();
+    match res {
+        ContractError::Unauthorized {} => {},
+        _ => panic!("Must return unauthorized error"),
+    }
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        ExecuteMsg::UpdateConfig {admin: Some(String::from("new_admin")), fee_bps: Some(100), confirmation_window: None}
+    ).unwrap();
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::GetConfig {}).unwrap();
+    let value: ConfigResponse = from_binary(&res).unwrap();
+    assert_eq!(value.config.admin, "new_admin");
+    assert_eq!(value.config.fee_bps, 100);
+}
+
+#[test]
+fn cancel_payment_request() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+    create_request(deps.as_mut(), mock_env(), "host", 1_000_000, "uluna");
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("customer", &[]),
+        ExecuteMsg::CancelPaymentRequest {id: String::from("1")}
+    ).unwrap_err();
+    match res {
+        ContractError::Unauthorized {} => {},
+        _ => panic!("Must return unauthorized error"),
+    }
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("host", &[]),
+        ExecuteMsg::CancelPaymentRequest {id: String::from("1")}
+    ).unwrap();
+    let pr = payment_requests().load(&deps.storage, request_key("1").unwrap()).unwrap();
+    assert_eq!(pr.status, PaymentRequestStatus::Cancelled);
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("customer", &coins(1_000_000, "uluna")),
+        ExecuteMsg::PayIntoPaymentRequest {id: String::from("1")}
+    ).unwrap_err();
+    match res {
+        ContractError::Cancelled {} => {},
+        _ => panic!("Must return cancelled error"),
+    }
+}
+
+#[test]
+fn update_payment_request() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+    create_request(deps.as_mut(), mock_env(), "host", 1_000_000, "uluna");
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("host", &[]),
+        ExecuteMsg::UpdatePaymentRequest {id: String::from("1"), assets: Some(vec![uluna(2_000_000)]), order_id: Some(String::from("2"))}
+    ).unwrap();
+    let pr = payment_requests().load(&deps.storage, request_key("1").unwrap()).unwrap();
+    assert_eq!(pr.assets, vec![uluna(2_000_000)]);
+    assert_eq!(pr.order_id, "2");
+
+    // once something is paid the request can no longer be changed
+    pay_request(deps.as_mut(), mock_env(), "customer", "1", 1_000_000, "uluna");
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("host", &[]),
+        ExecuteMsg::UpdatePaymentRequest {id: String::from("1"), assets: Some(vec![uluna(1)]), order_id: None}
+    ).unwrap_err();
+    match res {
+        ContractError::AlreadyPaid {} => {},
+        _ => panic!("Must return already paid error"),
+    }
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("host", &[]),
+        ExecuteMsg::CancelPaymentRequest {id: String::from("1")}
+    ).unwrap_err();
+    match res {
+        ContractError::AlreadyPaid {} => {},
+        _ => panic!("Must return already paid error"),
+    }
+}
+
+#[test]
+fn list_payment_requests() {
+    let mut deps = mock_dependencies(&coins(2, "token"));
+
+    let msg = InstantiateMsg {
+        shop: Addr::unchecked("shop"),
+        arbiter: None,
+        fee_bps: 0,
+        confirmation_window: 0,
+        receipt_code_id: None,
+    };
+    let info = mock_info("creator", &coins(1000, "earth"));
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+    approve_merchant(deps.as_mut(), "merchant1");
+    approve_merchant(deps.as_mut(), "merchant2");
+
+    let asset: Asset = Asset {
+        info: AssetInfo::NativeToken{ denom: String::from("uluna") },
+        amount: Uint128::from(1_000_000u128),
+    };
+    for merchant in ["merchant1", "merchant2", "merchant1"] {
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info(merchant, &[]),
+            ExecuteMsg::CreatePaymentRequest {assets: vec![asset.clone()], order_id: String::from("1"), expires: None}
+        ).unwrap();
+    }
+
+    let pay_info = mock_info("customer", &coins(1_000_000, "uluna"));
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        pay_info,
+        ExecuteMsg::PayIntoPaymentRequest {id: String::from("3")}
+    ).unwrap();
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::ListByMerchant {
+        merchant: String::from("merchant1"),
+        start_after: None,
+        limit: None,
+    }).unwrap();
+    let value: PaymentRequestsResponse = from_binary(&res).unwrap();
+    let ids: Vec<String> = value.payment_requests.into_iter().map(|p| p.id).collect();
+    assert_eq!(ids, vec!["1", "3"]);
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::ListByMerchant {
+        merchant: String::from("merchant1"),
+        start_after: Some(String::from("1")),
+        limit: Some(1),
+    }).unwrap();
+    let value: PaymentRequestsResponse = from_binary(&res).unwrap();
+    let ids: Vec<String> = value.payment_requests.into_iter().map(|p| p.id).collect();
+    assert_eq!(ids, vec!["3"]);
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::ListByCustomer {
+        customer: String::from("customer"),
+        start_after: None,
+        limit: None,
+    }).unwrap();
+    let value: PaymentRequestsResponse = from_binary(&res).unwrap();
+    let ids: Vec<String> = value.payment_requests.into_iter().map(|p| p.id).collect();
+    assert_eq!(ids, vec!["3"]);
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::ListByStatus {
+        status: PaymentRequestStatus::Unpaid,
+        start_after: None,
+        limit: Some(1),
+    }).unwrap();
+    let value: PaymentRequestsResponse = from_binary(&res).unwrap();
+    let ids: Vec<String> = value.payment_requests.into_iter().map(|p| p.id).collect();
+    assert_eq!(ids, vec!["1"]);
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::ListByStatus {
+        status: PaymentRequestStatus::Paid,
+        start_after: None,
+        limit: None,
+    }).unwrap();
+    let value: PaymentRequestsResponse = from_binary(&res).unwrap();
+    let ids: Vec<String> = value.payment_requests.into_iter().map(|p| p.id).collect();
+    assert_eq!(ids, vec!["3"]);
+
+    // ids past 9 list in creation order, not lexicographically
+    for _ in 0..9 {
+        create_request(deps.as_mut(), mock_env(), "merchant1", 1000, "uluna");
+    }
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::ListByMerchant {
+        merchant: String::from("merchant1"),
+        start_after: Some(String::from("3")),
+        limit: None,
+    }).unwrap();
+    let value: PaymentRequestsResponse = from_binary(&res).unwrap();
+    let ids: Vec<String> = value.payment_requests.into_iter().map(|p| p.id).collect();
+    assert_eq!(ids, vec!["4", "5", "6", "7", "8", "9", "10", "11", "12"]);
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::ListByStatus {
+        status: PaymentRequestStatus::Unpaid,
+        start_after: Some(String::from("9")),
+        limit: None,
+    }).unwrap();
+    let value: PaymentRequestsResponse = from_binary(&res).unwrap();
+    let ids: Vec<String> = value.payment_requests.into_iter().map(|p| p.id).collect();
+    assert_eq!(ids, vec!["10", "11", "12"]);
+}
+
+#[test]
+fn pay_into_expired_payment_request() {
+    let mut deps = mock_dependencies(&coins(2, "token"));
+
+    let msg = InstantiateMsg {
+        shop: Addr::unchecked("shop"),
+        arbiter: None,
+        fee_bps: 0,
+        confirmation_window: 0,
+        receipt_code_id: None,
+    };
+    let info = mock_info("creator", &coins(1000, "earth"));
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+    approve_merchant(deps.as_mut(), "host");
+
+    let asset: Asset = Asset {
+        info: AssetInfo::NativeToken{ denom: String::from("uluna") },
+        amount: Uint128::from(1_000_000u128),
+    };
+    let env = mock_env();
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("host", &[]),
+        ExecuteMsg::CreatePaymentRequest {
+            assets: vec![asset],
+            order_id: String::from("1"),
+            expires: Some(Expiration::AtHeight(env.block.height + 10)),
+        }
+    ).unwrap();
+
+    let mut expired_env = mock_env();
+    expired_env.block.height += 10;
+    let res = execute(
+        deps.as_mut(),
+        expired_env,
+        mock_info("customer", &coins(1_000_000, "uluna")),
+        ExecuteMsg::PayIntoPaymentRequest {id: String::from("1")}
+    ).unwrap_err();
+    match res {
+        ContractError::Expired {} => {},
+        _ => panic!("Must return expired error"),
+    }
+}
+
+#[test]
+fn reclaim_after_expiry() {
+    let mut deps = mock_dependencies(&coins(2, "token"));
+
+    let msg = InstantiateMsg {
+        shop: Addr::unchecked("shop"),
+        arbiter: None,
+        fee_bps: 0,
+        confirmation_window: 0,
+        receipt_code_id: None,
+    };
+    let info = mock_info("creator", &coins(1000, "earth"));
+    let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+    approve_merchant(deps.as_mut(), "host");
+
+    let asset: Asset = Asset {
+        info: AssetInfo::NativeToken{ denom: String::from("uluna") },
+        amount: Uint128::from(1_000_000u128),
+    };
+    let env = mock_env();
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("host", &[]),
+        ExecuteMsg::CreatePaymentRequest {
+            assets: vec![asset],
+            order_id: String::from("1"),
+            expires: Some(Expiration::AtHeight(env.block.height + 10)),
+        }
+    ).unwrap();
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("customer", &coins(1_000_000, "uluna")),
+        ExecuteMsg::PayIntoPaymentRequest {id: String::from("1")}
+    ).unwrap();
+
+    // cannot reclaim before the deadline
+    let res = execute(
+        deps.as_mut(),
+        env,
+        mock_info("customer", &[]),
+        ExecuteMsg::Reclaim {id: String::from("1")}
+    ).unwrap_err();
+    match res {
+        ContractError::NotExpired {} => {},
+        _ => panic!("Must return not expired error"),
+    }
+
+    let mut expired_env = mock_env();
+    expired_env.block.height += 10;
+
+    // only the customer can reclaim
+    let res = execute(
+        deps.as_mut(),
+        expired_env.clone(),
+        mock_info("host", &[]),
+        ExecuteMsg::Reclaim {id: String::from("1")}
+    ).unwrap_err();
+    match res {
+        ContractError::Unauthorized {} => {},
+        _ => panic!("Must return unauthorized error"),
+    }
+
+    let res = execute(
+        deps.as_mut(),
+        expired_env,
+        mock_info("customer", &[]),
+        ExecuteMsg::Reclaim {id: String::from("1")}
+    ).unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: String::from("customer"),
+        amount: coins(1_000_000, "uluna"),
+    }));
+    assert!(payment_requests().may_load(&deps.storage, request_key("1").unwrap()).unwrap().is_none());
+}
+
+#[test]
+fn dispute_freezes_settlement() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+    create_request(deps.as_mut(), mock_env(), "host", 1_000_000, "uluna");
+    pay_request(deps.as_mut(), mock_env(), "customer", "1", 1_000_000, "uluna");
+
+    // outsiders cannot raise a dispute
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::RaiseDispute {id: String::from("1"), reason: String::from("spam")}
+    ).unwrap_err();
+    match res {
+        ContractError::Unauthorized {} => {},
+        _ => panic!("Must return unauthorized error"),
+    }
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("customer", &[]),
+        ExecuteMsg::RaiseDispute {id: String::from("1"), reason: String::from("item not delivered")}
+    ).unwrap();
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("customer", &[]),
+        ExecuteMsg::SettlePaymentRequest {id: String::from("1")}
+    ).unwrap_err();
+    match res {
+        ContractError::Disputed {} => {},
+        _ => panic!("Must return disputed error"),
+    }
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::ListOpenDisputes {start_after: None, limit: None}).unwrap();
+    let value: DisputesResponse = from_binary(&res).unwrap();
+    assert_eq!(value.disputes.len(), 1);
+    assert_eq!(value.disputes[0].raised_by, "customer");
+    assert_eq!(value.disputes[0].reason, "item not delivered");
+}
+
+#[test]
+fn resolve_dispute() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+    create_request(deps.as_mut(), mock_env(), "host", 1_000_000, "uluna");
+    pay_request(deps.as_mut(), mock_env(), "customer", "1", 1_000_000, "uluna");
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("host", &[]),
+        ExecuteMsg::RaiseDispute {id: String::from("1"), reason: String::from("chargeback")}
+    ).unwrap();
+
+    // only the shop or the arbiter can resolve
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("host", &[]),
+        ExecuteMsg::ResolveDispute {id: String::from("1"), merchant_assets: vec![uluna(1_000_000)]}
+    ).unwrap_err();
+    match res {
+        ContractError::Unauthorized {} => {},
+        _ => panic!("Must return unauthorized error"),
+    }
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("arbiter", &[]),
+        ExecuteMsg::ResolveDispute {id: String::from("1"), merchant_assets: vec![uluna(400_000)]}
+    ).unwrap();
+    assert_eq!(res.messages.len(), 2);
+    assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: String::from("host"),
+        amount: coins(400_000, "uluna"),
+    }));
+    assert_eq!(res.messages[1].msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: String::from("customer"),
+        amount: coins(600_000, "uluna"),
+    }));
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::GetDispute {id: String::from("1")}).unwrap();
+    let value: DisputeResponse = from_binary(&res).unwrap();
+    let resolution = value.dispute.resolution.unwrap();
+    assert_eq!(resolution.resolved_by, "arbiter");
+    assert_eq!(resolution.customer_assets, vec![uluna(600_000)]);
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::ListOpenDisputes {start_after: None, limit: None}).unwrap();
+    let value: DisputesResponse = from_binary(&res).unwrap();
+    assert!(value.disputes.is_empty());
+}
+
+#[test]
+fn pay_signed_invoice() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+    register_merchant_key(deps.as_mut());
+
+    let key = merchant_key();
+    let invoice = invoice(1);
+    let signature = sign_invoice(&key, &invoice);
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("customer", &coins(1000000, "uluna")),
+        ExecuteMsg::PayInvoice { invoice: invoice.clone(), signature: signature.clone() },
+    ).unwrap();
+    assert_eq!(0, res.messages.len());
+
+    let p = payment_requests().load(&deps.storage, request_key("1").unwrap()).unwrap();
+    assert_eq!(p.merchant, Addr::unchecked("merchant"));
+    assert_eq!(p.customer, Addr::unchecked("customer"));
+    assert_eq!(p.order_id, "inv-1");
+    assert_eq!(p.status, PaymentRequestStatus::Paid);
+    assert_eq!(p.expires, Some(invoice.expires));
+
+    // the same invoice cannot be paid twice
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("customer", &coins(1000000, "uluna")),
+        ExecuteMsg::PayInvoice { invoice, signature },
+    ).unwrap_err();
+    match res {
+        ContractError::NonceUsed { nonce: 1 } => {},
+        _ => panic!("Must return nonce used error"),
+    }
+}
+
+#[test]
+fn pay_invoice_with_invalid_signature() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+
+    let key = merchant_key();
+    let signature = sign_invoice(&key, &invoice(1));
+
+    // merchant has not registered a key yet
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("customer", &coins(1000000, "uluna")),
+        ExecuteMsg::PayInvoice { invoice: invoice(1), signature: signature.clone() },
+    ).unwrap_err();
+    match res {
+        ContractError::InvalidPubkey {} => {},
+        _ => panic!("Must return invalid pubkey error"),
+    }
+
+    register_merchant_key(deps.as_mut());
+
+    // tampered amount
+    let mut tampered = invoice(1);
+    tampered.assets = vec![uluna(1)];
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("customer", &coins(1, "uluna")),
+        ExecuteMsg::PayInvoice { invoice: tampered, signature: signature.clone() },
+    ).unwrap_err();
+    match res {
+        ContractError::InvalidSignature {} => {},
+        _ => panic!("Must return invalid signature error"),
+    }
+
+    // signed by someone else
+    let other = SigningKey::from_bytes(&[9u8; 32]).unwrap();
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("customer", &coins(1000000, "uluna")),
+        ExecuteMsg::PayInvoice { invoice: invoice(1), signature: sign_invoice(&other, &invoice(1)) },
+    ).unwrap_err();
+    match res {
+        ContractError::InvalidSignature {} => {},
+        _ => panic!("Must return invalid signature error"),
+    }
+
+    // signed for another escrow deployment or chain
+    let mut other_contract = mock_env();
+    other_contract.contract.address = Addr::unchecked("other_escrow");
+    let mut other_chain = mock_env();
+    other_chain.block.chain_id = String::from("other-chain");
+    for env in [other_contract, other_chain] {
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("customer", &coins(1000000, "uluna")),
+            ExecuteMsg::PayInvoice { invoice: invoice(1), signature: sign_invoice_for(&key, &invoice(1), &env) },
+        ).unwrap_err();
+        match res {
+            ContractError::InvalidSignature {} => {},
+            _ => panic!("Must return invalid signature error"),
+        }
+    }
+
+    // expired invoice
+    let mut env = mock_env();
+    env.block.height += 100;
+    let res = execute(
+        deps.as_mut(),
+        env,
+        mock_info("customer", &coins(1000000, "uluna")),
+        ExecuteMsg::PayInvoice { invoice: invoice(1), signature },
+    ).unwrap_err();
+    match res {
+        ContractError::Expired {} => {},
+        _ => panic!("Must return expired error"),
+    }
+}
+
+#[test]
+fn pay_invoice_with_cw20() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+    register_merchant_key(deps.as_mut());
+
+    let key = merchant_key();
+    let mut invoice = invoice(2);
+    invoice.assets = vec![Asset {
+        info: AssetInfo::Token { contract_addr: String::from("token") },
+        amount: Uint128::from(100u128),
+    }];
+    let signature = sign_invoice(&key, &invoice);
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("token", &[]),
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: String::from("customer"),
+            amount: Uint128::from(150u128),
+            msg: to_binary(&Cw20HookMsg::PayInvoice { invoice, signature }).unwrap(),
+        }),
+    ).unwrap();
+    assert_eq!(res.messages[0].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: String::from("token"),
+        funds: vec![],
+        msg: to_binary(&Cw20ExecuteMsg::Transfer {
+            recipient: String::from("customer"),
+            amount: Uint128::from(50u128),
+        }).unwrap(),
+    }));
+
+    let p = payment_requests().load(&deps.storage, request_key("1").unwrap()).unwrap();
+    assert_eq!(p.status, PaymentRequestStatus::Paid);
+}
+
+fn token_plan() -> ExecuteMsg {
+    ExecuteMsg::CreatePlan {
+        asset: Asset {
+            info: AssetInfo::Token { contract_addr: String::from("token") },
+            amount: Uint128::from(100u128),
+        },
+        period: 30 * 24 * 60 * 60,
+    }
+}
+
+fn transfer_from(owner: &str, recipient: &str, amount: u128) -> CosmosMsg {
+    CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: String::from("token"),
+        funds: vec![],
+        msg: to_binary(&Cw20ExecuteMsg::TransferFrom {
+            owner: String::from(owner),
+            recipient: String::from(recipient),
+            amount: Uint128::from(amount),
+        }).unwrap(),
+    })
+}
+
+#[test]
+fn subscribe_and_collect() {
+    let mut deps = test_utils::mock_dependencies();
+    init_contract(deps.as_mut(), mock_env());
+    execute(deps.as_mut(), mock_env(), mock_info("merchant", &[]), token_plan()).unwrap();
+
+    // no allowance granted yet
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("subscriber", &[]),
+        ExecuteMsg::Subscribe { plan_id: String::from("1") },
+    ).unwrap_err();
+    match res {
+        ContractError::InsufficientAllowance {} => {},
+        _ => panic!("Must return insufficient allowance error"),
+    }
+
+    deps.querier.token_querier.set_allowance("token", "subscriber", 1000);
+    deps.querier.token_querier.set_balance("token", "subscriber", 1000);
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("subscriber", &[]),
+        ExecuteMsg::Subscribe { plan_id: String::from("1") },
+    ).unwrap();
+    assert_eq!(res.messages[0].msg, transfer_from("subscriber", "merchant", 100));
+
+    // the next period is not due yet
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::Collect { subscription_id: String::from("1") },
+    ).unwrap_err();
+    match res {
+        ContractError::NotDue {} => {},
+        _ => panic!("Must return not due error"),
+    }
+
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(30 * 24 * 60 * 60);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::Collect { subscription_id: String::from("1") },
+    ).unwrap();
+    assert_eq!(res.messages[0].msg, transfer_from("subscriber", "merchant", 100));
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::GetSubscription { subscription_id: String::from("1") }).unwrap();
+    let value: SubscriptionResponse = from_binary(&res).unwrap();
+    assert_eq!(value.subscription.next_collection, env.block.time.plus_seconds(30 * 24 * 60 * 60));
+    assert_eq!(value.subscription.status, SubscriptionStatus::Active);
+}
+
+#[test]
+fn subscription_lapses_after_missed_payments() {
+    let mut deps = test_utils::mock_dependencies();
+    init_contract(deps.as_mut(), mock_env());
+    execute(deps.as_mut(), mock_env(), mock_info("merchant", &[]), token_plan()).unwrap();
+    deps.querier.token_querier.set_allowance("token", "subscriber", 100);
+    deps.querier.token_querier.set_balance("token", "subscriber", 100);
+    execute(deps.as_mut(), mock_env(), mock_info("subscriber", &[]), ExecuteMsg::Subscribe { plan_id: String::from("1") }).unwrap();
+
+    // allowance used up by the first period
+    deps.querier.token_querier.set_allowance("token", "subscriber", 0);
+    let mut env = mock_env();
+    for missed in 1..4 {
+        env.block.time = env.block.time.plus_seconds(30 * 24 * 60 * 60);
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("anyone", &[]),
+            ExecuteMsg::Collect { subscription_id: String::from("1") },
+        ).unwrap();
+        assert_eq!(0, res.messages.len());
+        assert_eq!(res.attributes[2].value, missed.to_string());
+    }
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::GetSubscription { subscription_id: String::from("1") }).unwrap();
+    let value: SubscriptionResponse = from_binary(&res).unwrap();
+    assert_eq!(value.subscription.status, SubscriptionStatus::Lapsed);
+
+    env.block.time = env.block.time.plus_seconds(30 * 24 * 60 * 60);
+    let res = execute(
+        deps.as_mut(),
+        env,
+        mock_info("anyone", &[]),
+        ExecuteMsg::Collect { subscription_id: String::from("1") },
+    ).unwrap_err();
+    match res {
+        ContractError::SubscriptionInactive {} => {},
+        _ => panic!("Must return subscription inactive error"),
+    }
+}
+
+#[test]
+fn cancel_subscription() {
+    let mut deps = test_utils::mock_dependencies();
+    init_contract(deps.as_mut(), mock_env());
+    execute(deps.as_mut(), mock_env(), mock_info("merchant", &[]), token_plan()).unwrap();
+    deps.querier.token_querier.set_allowance("token", "subscriber", 1000);
+    deps.querier.token_querier.set_balance("token", "subscriber", 1000);
+    execute(deps.as_mut(), mock_env(), mock_info("subscriber", &[]), ExecuteMsg::Subscribe { plan_id: String::from("1") }).unwrap();
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::CancelSubscription { subscription_id: String::from("1") },
+    ).unwrap_err();
+    match res {
+        ContractError::Unauthorized {} => {},
+        _ => panic!("Must return unauthorized error"),
+    }
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("subscriber", &[]),
+        ExecuteMsg::CancelSubscription { subscription_id: String::from("1") },
+    ).unwrap();
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::ListSubscriptionsBySubscriber {
+        subscriber: String::from("subscriber"),
+        start_after: None,
+        limit: None,
+    }).unwrap();
+    let value: SubscriptionsResponse = from_binary(&res).unwrap();
+    assert_eq!(value.subscriptions.len(), 1);
+    assert_eq!(value.subscriptions[0].status, SubscriptionStatus::Cancelled);
+
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(30 * 24 * 60 * 60);
+    let res = execute(
+        deps.as_mut(),
+        env,
+        mock_info("anyone", &[]),
+        ExecuteMsg::Collect { subscription_id: String::from("1") },
+    ).unwrap_err();
+    match res {
+        ContractError::SubscriptionInactive {} => {},
+        _ => panic!("Must return subscription inactive error"),
+    }
+}
+
+#[test]
+fn subscription_skips_zero_merchant_share() {
+    let mut deps = test_utils::mock_dependencies();
+    init_contract(deps.as_mut(), mock_env());
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        ExecuteMsg::SetMerchantFee {merchant: String::from("merchant"), fee_bps: Some(10_000)}
+    ).unwrap();
+    execute(deps.as_mut(), mock_env(), mock_info("merchant", &[]), token_plan()).unwrap();
+    deps.querier.token_querier.set_allowance("token", "subscriber", 1000);
+    deps.querier.token_querier.set_balance("token", "subscriber", 1000);
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("subscriber", &[]),
+        ExecuteMsg::Subscribe { plan_id: String::from("1") },
+    ).unwrap();
+    assert_eq!(1, res.messages.len());
+    assert_eq!(res.messages[0].msg, transfer_from("subscriber", "shop", 100));
+}
+
+#[test]
+fn list_subscriptions_in_creation_order() {
+    let mut deps = test_utils::mock_dependencies();
+    init_contract(deps.as_mut(), mock_env());
+    deps.querier.token_querier.set_allowance("token", "subscriber", 10000);
+    deps.querier.token_querier.set_balance("token", "subscriber", 10000);
+    for plan_id in 1..12 {
+        execute(deps.as_mut(), mock_env(), mock_info("merchant", &[]), token_plan()).unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("subscriber", &[]),
+            ExecuteMsg::Subscribe { plan_id: plan_id.to_string() },
+        ).unwrap();
+    }
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::ListSubscriptionsByMerchant {
+        merchant: String::from("merchant"),
+        start_after: None,
+        limit: None,
+    }).unwrap();
+    let value: SubscriptionsResponse = from_binary(&res).unwrap();
+    let ids: Vec<String> = value.subscriptions.iter().map(|s| s.id.clone()).collect();
+    assert_eq!(ids, (1..11).map(|i| i.to_string()).collect::<Vec<String>>());
+    assert_eq!(value.subscriptions[9].plan_id, "10");
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::ListSubscriptionsBySubscriber {
+        subscriber: String::from("subscriber"),
+        start_after: Some(String::from("9")),
+        limit: None,
+    }).unwrap();
+    let value: SubscriptionsResponse = from_binary(&res).unwrap();
+    let ids: Vec<String> = value.subscriptions.iter().map(|s| s.id.clone()).collect();
+    assert_eq!(ids, vec!["10", "11"]);
+}
+
+fn create_htlc(deps: DepsMut, preimage: &[u8]) {
+    execute(
+        deps,
+        mock_env(),
+        mock_info("merchant", &[]),
+        ExecuteMsg::CreateHtlc {
+            assets: vec![uluna(1000000)],
+            order_id: String::from("swap-1"),
+            hashlock: Binary::from(Sha256::digest(preimage).as_slice()),
+            expires: Expiration::AtHeight(mock_env().block.height + 100),
+        },
+    ).unwrap();
+}
+
+#[test]
+fn claim_htlc_with_preimage() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+    create_htlc(deps.as_mut(), b"secret");
+    pay_request(deps.as_mut(), mock_env(), "customer", "1", 1000000, "uluna");
+
+    // the customer cannot release it without the preimage
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("customer", &[]),
+        ExecuteMsg::SettlePaymentRequest { id: String::from("1") },
+    ).unwrap_err();
+    match res {
+        ContractError::HashLocked {} => {},
+        _ => panic!("Must return hash locked error"),
+    }
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::ClaimHtlc { id: String::from("1"), preimage: Binary::from(b"guess") },
+    ).unwrap_err();
+    match res {
+        ContractError::InvalidPreimage {} => {},
+        _ => panic!("Must return invalid preimage error"),
+    }
+
+    STATE.update(&mut deps.storage, |mut state| -> StdResult<_> {
+        state.receipt_contract = Some(Addr::unchecked("receipts"));
+        Ok(state)
+    }).unwrap();
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::ClaimHtlc { id: String::from("1"), preimage: Binary::from(b"secret") },
+    ).unwrap();
+    assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: String::from("merchant"),
+        amount: coins(1000000, "uluna"),
+    }));
+    assert_eq!(res.messages[1].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: String::from("receipts"),
+        msg: to_binary(&Cw721ExecuteMsg::Mint(MintMsg {
+            token_id: String::from("1"),
+            owner: String::from("customer"),
+            token_uri: None,
+            extension: ReceiptMetadata {
+                payment_request_id: String::from("1"),
+                merchant: Addr::unchecked("merchant"),
+                order_id: String::from("swap-1"),
+                assets: vec![uluna(1000000)],
+                settled_at: mock_env().block.time,
+            },
+        })).unwrap(),
+        funds: vec![],
+    }));
+    assert!(res.attributes.iter().any(|a| a.key == "preimage" && a.value == Binary::from(b"secret").to_base64()));
+    assert!(payment_requests().may_load(&deps.storage, request_key("1").unwrap()).unwrap().is_none());
+}
+
+#[test]
+fn reclaim_htlc_after_timeout() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+    create_htlc(deps.as_mut(), b"secret");
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("merchant", &[]),
+        ExecuteMsg::CreateHtlc {
+            assets: vec![uluna(1000000)],
+            order_id: String::from("swap-2"),
+            hashlock: Binary::from(Sha256::digest(b"secret").as_slice()),
+            expires: Expiration::Never {},
+        },
+    ).unwrap_err();
+    match res {
+        ContractError::InvalidExpiration {} => {},
+        _ => panic!("Must return invalid expiration error"),
+    }
+    pay_request(deps.as_mut(), mock_env(), "customer", "1", 1000000, "uluna");
+
+    let mut env = mock_env();
+    env.block.height += 100;
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::ClaimHtlc { id: String::from("1"), preimage: Binary::from(b"secret") },
+    ).unwrap_err();
+    match res {
+        ContractError::Expired {} => {},
+        _ => panic!("Must return expired error"),
+    }
+
+    let res = execute(
+        deps.as_mut(),
+        env,
+        mock_info("customer", &[]),
+        ExecuteMsg::Reclaim { id: String::from("1") },
+    ).unwrap();
+    assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: String::from("customer"),
+        amount: coins(1000000, "uluna"),
+    }));
+}
+
+fn asset(denom: &str, amount: u128) -> Asset {
+    Asset {
+        info: AssetInfo::NativeToken { denom: String::from(denom) },
+        amount: Uint128::from(amount),
+    }
+}
+
+fn bank_send(to: &str, amount: u128, denom: &str) -> CosmosMsg {
+    CosmosMsg::Bank(BankMsg::Send {
+        to_address: String::from(to),
+        amount: coins(amount, denom),
+    })
+}
+
+#[test]
+fn fill_offer_with_cw20() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("maker", &coins(1000, "uluna")),
+        ExecuteMsg::CreateOffer {
+            ask: Asset {
+                info: AssetInfo::Token { contract_addr: String::from("token") },
+                amount: Uint128::from(500u128),
+            },
+            counterparty: Some(String::from("taker")),
+            partial_fills: false,
+        },
+    ).unwrap();
+
+    let fill = |sender: &str| ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: String::from(sender),
+        amount: Uint128::from(600u128),
+        msg: to_binary(&Cw20HookMsg::FillOffer { id: String::from("1") }).unwrap(),
+    });
+
+    // only the named counterparty can fill
+    let res = execute(deps.as_mut(), mock_env(), mock_info("token", &[]), fill("someone")).unwrap_err();
+    match res {
+        ContractError::Unauthorized {} => {},
+        _ => panic!("Must return unauthorized error"),
+    }
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info("token", &[]), fill("taker")).unwrap();
+    let token_transfer = |recipient: &str, amount: u128| CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: String::from("token"),
+        funds: vec![],
+        msg: to_binary(&Cw20ExecuteMsg::Transfer {
+            recipient: String::from(recipient),
+            amount: Uint128::from(amount),
+        }).unwrap(),
+    });
+    assert_eq!(res.messages[0].msg, token_transfer("maker", 500));
+    assert_eq!(res.messages[1].msg, bank_send("taker", 1000, "uluna"));
+    assert_eq!(res.messages[2].msg, token_transfer("taker", 100));
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::ListOffers { start_after: None, limit: None }).unwrap();
+    let value: OffersResponse = from_binary(&res).unwrap();
+    assert_eq!(value.offers.len(), 0);
+}
+
+#[test]
+fn partially_fill_offer() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("maker", &coins(1000, "uluna")),
+        ExecuteMsg::CreateOffer { ask: asset("uusd", 300), counterparty: None, partial_fills: false },
+    ).unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("maker", &coins(1000, "uluna")),
+        ExecuteMsg::CreateOffer { ask: asset("uusd", 300), counterparty: None, partial_fills: true },
+    ).unwrap();
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("taker", &coins(100, "uusd")),
+        ExecuteMsg::FillOffer { id: String::from("1") },
+    ).unwrap_err();
+    match res {
+        ContractError::PartialFillNotAllowed {} => {},
+        _ => panic!("Must return partial fill not allowed error"),
+    }
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("taker", &coins(100, "uusd")),
+        ExecuteMsg::FillOffer { id: String::from("2") },
+    ).unwrap();
+    assert_eq!(res.messages[0].msg, bank_send("maker", 100, "uusd"));
+    assert_eq!(res.messages[1].msg, bank_send("taker", 333, "uluna"));
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::GetOffer { id: String::from("2") }).unwrap();
+    let value: OfferResponse = from_binary(&res).unwrap();
+    assert_eq!(value.offer.filled, Uint128::from(100u128));
+
+    // the last fill receives the rounding remainder
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("other_taker", &coins(200, "uusd")),
+        ExecuteMsg::FillOffer { id: String::from("2") },
+    ).unwrap();
+    assert_eq!(res.messages[0].msg, bank_send("maker", 200, "uusd"));
+    assert_eq!(res.messages[1].msg, bank_send("other_taker", 667, "uluna"));
+}
+
+#[test]
+fn reject_dust_offer_fill() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("maker", &coins(10, "uluna")),
+        ExecuteMsg::CreateOffer { ask: asset("uusd", 1000), counterparty: None, partial_fills: true },
+    ).unwrap();
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("taker", &coins(50, "uusd")),
+        ExecuteMsg::FillOffer { id: String::from("1") },
+    ).unwrap_err();
+    match res {
+        ContractError::FillTooSmall {} => {},
+        _ => panic!("Must return fill too small error"),
+    }
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("taker", &coins(100, "uusd")),
+        ExecuteMsg::FillOffer { id: String::from("1") },
+    ).unwrap();
+    assert_eq!(res.messages[0].msg, bank_send("maker", 100, "uusd"));
+    assert_eq!(res.messages[1].msg, bank_send("taker", 1, "uluna"));
+}
+
+#[test]
+fn list_offers_in_creation_order() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+    for _ in 0..11 {
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("maker", &coins(1000, "uluna")),
+            ExecuteMsg::CreateOffer { ask: asset("uusd", 300), counterparty: None, partial_fills: true },
+        ).unwrap();
+    }
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::ListOffers { start_after: None, limit: None }).unwrap();
+    let value: OffersResponse = from_binary(&res).unwrap();
+    let ids: Vec<String> = value.offers.into_iter().map(|o| o.id).collect();
+    assert_eq!(ids, vec!["1", "2", "3", "4", "5", "6", "7", "8", "9", "10"]);
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::ListOffers { start_after: Some(String::from("9")), limit: None }).unwrap();
+    let value: OffersResponse = from_binary(&res).unwrap();
+    let ids: Vec<String> = value.offers.into_iter().map(|o| o.id).collect();
+    assert_eq!(ids, vec!["10", "11"]);
+}
+
+#[test]
+fn cancel_offer() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("maker", &coins(1000, "uluna")),
+        ExecuteMsg::CreateOffer { ask: asset("uusd", 500), counterparty: None, partial_fills: true },
+    ).unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("taker", &coins(250, "uusd")),
+        ExecuteMsg::FillOffer { id: String::from("1") },
+    ).unwrap();
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("taker", &[]),
+        ExecuteMsg::CancelOffer { id: String::from("1") },
+    ).unwrap_err();
+    match res {
+        ContractError::Unauthorized {} => {},
+        _ => panic!("Must return unauthorized error"),
+    }
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("maker", &[]),
+        ExecuteMsg::CancelOffer { id: String::from("1") },
+    ).unwrap();
+    assert_eq!(res.messages[0].msg, bank_send("maker", 500, "uluna"));
+}
+
+#[test]
+fn set_invalid_milestones() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+    create_request(deps.as_mut(), mock_env(), "merchant", 1000, "uluna");
+
+    for milestones in [
+        vec![MilestoneShare::Bps(5000)],
+        vec![MilestoneShare::Bps(5000), MilestoneShare::Assets(vec![uluna(600)])],
+        vec![MilestoneShare::Bps(5000), MilestoneShare::Assets(vec![asset("uusd", 500)])],
+    ] {
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("merchant", &[]),
+            ExecuteMsg::SetMilestones { id: String::from("1"), milestones },
+        ).unwrap_err();
+        match res {
+            ContractError::InvalidMilestones {} => {},
+            _ => panic!("Must return invalid milestones error"),
+        }
+    }
+}
+
+#[test]
+fn release_approved_milestones() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+    create_request(deps.as_mut(), mock_env(), "merchant", 1000, "uluna");
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("merchant", &[]),
+        ExecuteMsg::SetMilestones {
+            id: String::from("1"),
+            milestones: vec![MilestoneShare::Bps(3333), MilestoneShare::Bps(3333), MilestoneShare::Bps(3334)],
+        },
+    ).unwrap();
+    pay_request(deps.as_mut(), mock_env(), "customer", "1", 1000, "uluna");
+
+    // rounding dust goes to the last milestone
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::GetMilestones { id: String::from("1") }).unwrap();
+    let value: MilestonesResponse = from_binary(&res).unwrap();
+    assert_eq!(value.milestones[0].assets, vec![uluna(333)]);
+    assert_eq!(value.milestones[2].assets, vec![uluna(334)]);
+
+    let settle = ExecuteMsg::SettlePaymentRequest { id: String::from("1") };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("customer", &[]), settle.clone()).unwrap_err();
+    match res {
+        ContractError::NoApprovedMilestones {} => {},
+        _ => panic!("Must return no approved milestones error"),
+    }
+
+    let approve = |milestone: u32| ExecuteMsg::ApproveMilestone { id: String::from("1"), milestone };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("merchant", &[]), approve(0)).unwrap_err();
+    match res {
+        ContractError::Unauthorized {} => {},
+        _ => panic!("Must return unauthorized error"),
+    }
+    execute(deps.as_mut(), mock_env(), mock_info("customer", &[]), approve(0)).unwrap();
+    let res = execute(deps.as_mut(), mock_env(), mock_info("customer", &[]), settle).unwrap();
+    assert_eq!(res.messages[0].msg, bank_send("merchant", 333, "uluna"));
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info("customer", &[]), approve(0)).unwrap_err();
+    match res {
+        ContractError::MilestoneNotPending { milestone: 0 } => {},
+        _ => panic!("Must return milestone not pending error"),
+    }
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::GetMilestones { id: String::from("1") }).unwrap();
+    let value: MilestonesResponse = from_binary(&res).unwrap();
+    assert_eq!(value.milestones[0].status, MilestoneStatus::Released);
+    assert_eq!(value.milestones[1].status, MilestoneStatus::Pending);
+
+    // only the unreleased remainder is under dispute
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("customer", &[]),
+        ExecuteMsg::RaiseDispute { id: String::from("1"), reason: String::from("late") },
+    ).unwrap();
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("arbiter", &[]),
+        ExecuteMsg::ResolveDispute { id: String::from("1"), merchant_assets: vec![uluna(167)] },
+    ).unwrap();
+    assert_eq!(res.messages[0].msg, bank_send("merchant", 167, "uluna"));
+    assert_eq!(res.messages[1].msg, bank_send("customer", 500, "uluna"));
+}
+
+#[test]
+fn release_after_confirmation_window() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        ExecuteMsg::UpdateConfig { admin: None, fee_bps: None, confirmation_window: Some(3 * 24 * 60 * 60) },
+    ).unwrap();
+    create_request(deps.as_mut(), mock_env(), "merchant", 1000, "uluna");
+    create_request(deps.as_mut(), mock_env(), "merchant", 1000, "uluna");
+    pay_request(deps.as_mut(), mock_env(), "customer", "1", 1000, "uluna");
+    pay_request(deps.as_mut(), mock_env(), "customer", "2", 1000, "uluna");
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("customer", &[]),
+        ExecuteMsg::RaiseDispute { id: String::from("2"), reason: String::from("not delivered") },
+    ).unwrap();
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("merchant", &[]),
+        ExecuteMsg::Release { id: String::from("1") },
+    ).unwrap_err();
+    match res {
+        ContractError::ConfirmationPending {} => {},
+        _ => panic!("Must return confirmation pending error"),
+    }
+
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(3 * 24 * 60 * 60);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("keeper", &[]),
+        ExecuteMsg::Release { id: String::from("1") },
+    ).unwrap();
+    assert_eq!(res.messages[0].msg, bank_send("merchant", 1000, "uluna"));
+    assert!(payment_requests().may_load(&deps.storage, request_key("1").unwrap()).unwrap().is_none());
+
+    // disputed requests wait for the arbiter
+    let res = execute(
+        deps.as_mut(),
+        env,
+        mock_info("keeper", &[]),
+        ExecuteMsg::Release { id: String::from("2") },
+    ).unwrap_err();
+    match res {
+        ContractError::Disputed {} => {},
+        _ => panic!("Must return disputed error"),
+    }
+}
+
+#[test]
+fn release_disabled_without_confirmation_window() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+    create_request(deps.as_mut(), mock_env(), "merchant", 1000, "uluna");
+    pay_request(deps.as_mut(), mock_env(), "customer", "1", 1000, "uluna");
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("keeper", &[]),
+        ExecuteMsg::Release { id: String::from("1") },
+    ).unwrap_err();
+    match res {
+        ContractError::ReleaseDisabled {} => {},
+        _ => panic!("Must return release disabled error"),
+    }
+
+    // the customer can still dispute
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("customer", &[]),
+        ExecuteMsg::RaiseDispute { id: String::from("1"), reason: String::from("not delivered") },
+    ).unwrap();
+}
+
+#[test]
+fn pull_payments_and_withdraw() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+    execute(deps.as_mut(), mock_env(), mock_info("merchant", &[]), ExecuteMsg::SetPullPayments { enabled: true }).unwrap();
+
+    create_request(deps.as_mut(), mock_env(), "merchant", 1000, "uluna");
+    create_request(deps.as_mut(), mock_env(), "merchant", 500, "uluna");
+    create_request(deps.as_mut(), mock_env(), "merchant", 200, "uusd");
+    for (id, amount, denom) in [("1", 1000, "uluna"), ("2", 500, "uluna"), ("3", 200, "uusd")] {
+        pay_request(deps.as_mut(), mock_env(), "customer", id, amount, denom);
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("customer", &[]),
+            ExecuteMsg::SettlePaymentRequest { id: String::from(id) },
+        ).unwrap();
+        assert_eq!(0, res.messages.len());
+    }
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::GetBalances { merchant: String::from("merchant") }).unwrap();
+    let value: BalancesResponse = from_binary(&res).unwrap();
+    assert_eq!(value.balances, vec![uluna(1500), asset("uusd", 200)]);
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("merchant", &[]),
+        ExecuteMsg::Withdraw { assets: Some(vec![uluna(2000)]) },
+    ).unwrap_err();
+    match res {
+        ContractError::InsufficientBalance {} => {},
+        _ => panic!("Must return insufficient balance error"),
+    }
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("merchant", &[]),
+        ExecuteMsg::Withdraw { assets: Some(vec![uluna(500)]) },
+    ).unwrap();
+    assert_eq!(res.messages[0].msg, bank_send("merchant", 500, "uluna"));
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("merchant", &[]),
+        ExecuteMsg::Withdraw { assets: None },
+    ).unwrap();
+    assert_eq!(res.messages.len(), 2);
+    assert_eq!(res.messages[0].msg, bank_send("merchant", 1000, "uluna"));
+    assert_eq!(res.messages[1].msg, bank_send("merchant", 200, "uusd"));
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::GetBalances { merchant: String::from("merchant") }).unwrap();
+    let value: BalancesResponse = from_binary(&res).unwrap();
+    assert_eq!(value.balances.len(), 0);
+}
+
+#[test]
+fn create_payment_requests_in_batch() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+    let request = |amount: u128| NewPaymentRequest {
+        assets: vec![uluna(amount)],
+        order_id: String::from("1"),
+        expires: None,
+    };
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("merchant", &[]),
+        ExecuteMsg::CreatePaymentRequests { requests: vec![request(100), request(200), request(300)] },
+    ).unwrap();
+    assert_eq!(res.attributes[1].value, "1,2,3");
+    let p = payment_requests().load(&deps.storage, request_key("3").unwrap()).unwrap();
+    assert_eq!(p.assets, vec![uluna(300)]);
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("merchant", &[]),
+        ExecuteMsg::CreatePaymentRequests { requests: vec![request(100), request(0)] },
+    ).unwrap_err();
+    match res {
+        ContractError::BatchItem { index: 1, .. } => {},
+        _ => panic!("Must return batch item error"),
+    }
+}
+
+#[test]
+fn settle_payment_requests_in_batch() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+    create_request(deps.as_mut(), mock_env(), "merchant", 1000, "uluna");
+    create_request(deps.as_mut(), mock_env(), "merchant", 500, "uluna");
+    create_request(deps.as_mut(), mock_env(), "other_merchant", 200, "uluna");
+    create_request(deps.as_mut(), mock_env(), "merchant", 300, "uluna");
+    pay_request(deps.as_mut(), mock_env(), "customer", "1", 1000, "uluna");
+    pay_request(deps.as_mut(), mock_env(), "customer", "2", 500, "uluna");
+    pay_request(deps.as_mut(), mock_env(), "customer", "3", 200, "uluna");
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("customer", &[]),
+        ExecuteMsg::SettlePaymentRequests { ids: vec![String::from("1"), String::from("3"), String::from("2")] },
+    ).unwrap();
+    assert_eq!(res.messages.len(), 2);
+    assert_eq!(res.messages[0].msg, bank_send("merchant", 1500, "uluna"));
+    assert_eq!(res.messages[1].msg, bank_send("other_merchant", 200, "uluna"));
+
+    // a request cannot be settled twice in the same batch
+    pay_request(deps.as_mut(), mock_env(), "customer", "4", 300, "uluna");
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("customer", &[]),
+        ExecuteMsg::SettlePaymentRequests { ids: vec![String::from("4"), String::from("4")] },
+    ).unwrap_err();
+    match res {
+        ContractError::BatchItem { index: 1, error } => assert_eq!(error, "payment_request not found"),
+        _ => panic!("Must return batch item error"),
+    }
+}
+
+#[test]
+fn mint_receipt_on_settlement() {
+    let mut deps = mock_dependencies(&[]);
+    let msg = InstantiateMsg {
+        shop: Addr::unchecked("shop"),
+        arbiter: None,
+        fee_bps: 0,
+        confirmation_window: 0,
+        receipt_code_id: Some(7),
+    };
+    let res = instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+    approve_merchant(deps.as_mut(), "merchant");
+    assert_eq!(res.messages[0].id, 1);
+    assert_eq!(res.messages[0].reply_on, ReplyOn::Success);
+    assert_eq!(res.messages[0].msg, CosmosMsg::Wasm(WasmMsg::Instantiate {
+        admin: None,
+        code_id: 7,
+        msg: to_binary(&Cw721InstantiateMsg {
+            name: String::from("escrow receipts"),
+            symbol: String::from("RCPT"),
+            minter: String::from(MOCK_CONTRACT_ADDR),
+        }).unwrap(),
+        funds: vec![],
+        label: String::from("crates.io:escrow receipts"),
+    }));
+
+    let mut instantiate_response = MsgInstantiateContractResponse::new();
+    instantiate_response.set_contract_address(String::from("receipts"));
+    reply(deps.as_mut(), mock_env(), Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(instantiate_response.write_to_bytes().unwrap().into()),
+        }),
+    }).unwrap();
+    let state = STATE.load(&deps.storage).unwrap();
+    assert_eq!(state.receipt_contract, Some(Addr::unchecked("receipts")));
+
+    // other replies cannot overwrite the receipt contract
+    let mut other_response = MsgInstantiateContractResponse::new();
+    other_response.set_contract_address(String::from("other"));
+    reply(deps.as_mut(), mock_env(), Reply {
+        id: 2,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(other_response.write_to_bytes().unwrap().into()),
+        }),
+    }).unwrap_err();
+    let state = STATE.load(&deps.storage).unwrap();
+    assert_eq!(state.receipt_contract, Some(Addr::unchecked("receipts")));
+
+    create_request(deps.as_mut(), mock_env(), "merchant", 1000, "uluna");
+    pay_request(deps.as_mut(), mock_env(), "customer", "1", 1000, "uluna");
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("customer", &[]),
+        ExecuteMsg::SettlePaymentRequest { id: String::from("1") },
+    ).unwrap();
+    assert_eq!(res.messages[0].msg, bank_send("merchant", 1000, "uluna"));
+    assert_eq!(res.messages[1].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: String::from("receipts"),
+        msg: to_binary(&Cw721ExecuteMsg::Mint(MintMsg {
+            token_id: String::from("1"),
+            owner: String::from("customer"),
+            token_uri: None,
+            extension: ReceiptMetadata {
+                payment_request_id: String::from("1"),
+                merchant: Addr::unchecked("merchant"),
+                order_id: String::from("1"),
+                assets: vec![uluna(1000)],
+                settled_at: mock_env().block.time,
+            },
+        })).unwrap(),
+        funds: vec![],
+    }));
+}
+
+#[test]
+fn merchant_registry() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+    let apply = ExecuteMsg::ApplyAsMerchant {
+        name: String::from("Shoe store"),
+        payout_address: Some(String::from("treasury")),
+        accepted_assets: vec![AssetInfo::NativeToken { denom: String::from("uusd") }],
+    };
+    execute(deps.as_mut(), mock_env(), mock_info("store", &[]), apply).unwrap();
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::GetMerchant { merchant: String::from("store") }).unwrap();
+    let value: MerchantResponse = from_binary(&res).unwrap();
+    assert_eq!(value.merchant.status, MerchantStatus::Pending);
+    assert_eq!(value.merchant.payout_address, Addr::unchecked("treasury"));
+
+    let create = ExecuteMsg::CreatePaymentRequest { assets: vec![asset("uusd", 1000)], order_id: String::from("1"), expires: None };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("store", &[]), create.clone()).unwrap_err();
+    match res {
+        ContractError::MerchantNotApproved {} => {},
+        _ => panic!("Must return merchant not approved error"),
+    }
+
+    // only the shop manages the registry
+    let approve = ExecuteMsg::ApproveMerchant { merchant: String::from("store") };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("store", &[]), approve.clone()).unwrap_err();
+    match res {
+        ContractError::Unauthorized {} => {},
+        _ => panic!("Must return unauthorized error"),
+    }
+    execute(deps.as_mut(), mock_env(), mock_info("shop", &[]), approve).unwrap();
+    execute(deps.as_mut(), mock_env(), mock_info("store", &[]), create.clone()).unwrap();
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("store", &[]),
+        ExecuteMsg::CreatePaymentRequest { assets: vec![uluna(1000)], order_id: String::from("2"), expires: None },
+    ).unwrap_err();
+    match res {
+        ContractError::AssetNotAccepted {} => {},
+        _ => panic!("Must return asset not accepted error"),
+    }
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("store", &[]),
+        ExecuteMsg::UpdatePaymentRequest { id: String::from("1"), assets: Some(vec![uluna(1000)]), order_id: None },
+    ).unwrap_err();
+    match res {
+        ContractError::AssetNotAccepted {} => {},
+        _ => panic!("Must return asset not accepted error"),
+    }
+
+    execute(deps.as_mut(), mock_env(), mock_info("shop", &[]), ExecuteMsg::SuspendMerchant { merchant: String::from("store") }).unwrap();
+    let res = execute(deps.as_mut(), mock_env(), mock_info("store", &[]), create).unwrap_err();
+    match res {
+        ContractError::MerchantNotApproved {} => {},
+        _ => panic!("Must return merchant not approved error"),
+    }
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("store", &[]),
+        ExecuteMsg::UpdatePaymentRequest { id: String::from("1"), assets: Some(vec![asset("uusd", 2000)]), order_id: None },
+    ).unwrap_err();
+    match res {
+        ContractError::MerchantNotApproved {} => {},
+        _ => panic!("Must return merchant not approved error"),
+    }
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::ListMerchants { start_after: Some(String::from("other_merchant")), limit: None }).unwrap();
+    let value: MerchantsResponse = from_binary(&res).unwrap();
+    let merchants: Vec<Addr> = value.merchants.into_iter().map(|m| m.address).collect();
+    assert_eq!(merchants, vec![Addr::unchecked("store")]);
+}
+
+#[test]
+fn settle_to_payout_address() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("merchant", &[]),
+        ExecuteMsg::ApplyAsMerchant { name: String::from("merchant"), payout_address: Some(String::from("treasury")), accepted_assets: vec![] },
+    ).unwrap();
+    create_request(deps.as_mut(), mock_env(), "merchant", 1000, "uluna");
+    pay_request(deps.as_mut(), mock_env(), "customer", "1", 1000, "uluna");
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info("customer", &[]), ExecuteMsg::SettlePaymentRequest { id: String::from("1") }).unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(res.messages[0].msg, bank_send("treasury", 1000, "uluna"));
+}
+
+#[test]
+fn pay_priced_payment_request() {
+    let mut deps = test_utils::mock_dependencies();
+    init_contract(deps.as_mut(), mock_env());
+    let luna = AssetInfo::NativeToken { denom: String::from("uluna") };
+    let token = AssetInfo::Token { contract_addr: String::from("token") };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("creator", &[]),
+        ExecuteMsg::SetPriceFeed { price_feed: Some(String::from("oracle")), assets: vec![luna.clone(), token.clone()], max_slippage_bps: 100 },
+    ).unwrap();
+    deps.querier.price_querier.set_price(&luna, Decimal::from_ratio(50u128, 1u128));
+    deps.querier.price_querier.set_price(&token, Decimal::percent(50));
+    for _ in 0..2 {
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("merchant", &[]),
+            ExecuteMsg::CreatePricedPaymentRequest { amount: Uint128::from(1_000_000u128), order_id: String::from("1"), expires: None },
+        ).unwrap();
+    }
+
+    // not whitelisted
+    let res = execute(deps.as_mut(), mock_env(), mock_info("customer", &coins(1_000_000, "uusd")), ExecuteMsg::PayIntoPaymentRequest { id: String::from("1") }).unwrap_err();
+    match res {
+        ContractError::WrongToken {} => {},
+        _ => panic!("Must return wrong token error"),
+    }
+
+    // 20_000 uluna are due, 19_700 is more than 1% short
+    let res = execute(deps.as_mut(), mock_env(), mock_info("customer", &coins(19_700, "uluna")), ExecuteMsg::PayIntoPaymentRequest { id: String::from("1") }).unwrap_err();
+    match res {
+        ContractError::SlippageExceeded {} => {},
+        _ => panic!("Must return slippage exceeded error"),
+    }
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info("customer", &coins(19_900, "uluna")), ExecuteMsg::PayIntoPaymentRequest { id: String::from("1") }).unwrap();
+    assert_eq!(res.messages.len(), 0);
+    let pr = payment_requests().load(&deps.storage, request_key("1").unwrap()).unwrap();
+    assert_eq!(pr.status, PaymentRequestStatus::Paid);
+    assert_eq!(pr.assets, vec![asset("uluna", 19_900)]);
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("token", &[]),
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: String::from("customer"),
+            amount: Uint128::from(2_100_000u128),
+            msg: to_binary(&Cw20HookMsg::PayIntoPaymentRequest { id: String::from("2") }).unwrap(),
+        }),
+    ).unwrap();
+    assert_eq!(res.messages[0].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: String::from("token"),
+        funds: vec![],
+        msg: to_binary(&Cw20ExecuteMsg::Transfer {
+            recipient: String::from("customer"),
+            amount: Uint128::from(100_000u128),
+        }).unwrap(),
+    }));
+    let pr = payment_requests().load(&deps.storage, request_key("2").unwrap()).unwrap();
+    assert_eq!(pr.status, PaymentRequestStatus::Paid);
+    assert_eq!(pr.paid_assets, vec![Asset { info: token, amount: Uint128::from(2_000_000u128) }]);
+
+    // whitelisted by the shop but not accepted by the merchant
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("luna_merchant", &[]),
+        ExecuteMsg::ApplyAsMerchant { name: String::from("luna_merchant"), payout_address: None, accepted_assets: vec![luna] },
+    ).unwrap();
+    execute(deps.as_mut(), mock_env(), mock_info("shop", &[]), ExecuteMsg::ApproveMerchant { merchant: String::from("luna_merchant") }).unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("luna_merchant", &[]),
+        ExecuteMsg::CreatePricedPaymentRequest { amount: Uint128::from(1_000_000u128), order_id: String::from("3"), expires: None },
+    ).unwrap();
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("token", &[]),
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: String::from("customer"),
+            amount: Uint128::from(2_000_000u128),
+            msg: to_binary(&Cw20HookMsg::PayIntoPaymentRequest { id: String::from("3") }).unwrap(),
+        }),
+    ).unwrap_err();
+    match res {
+        ContractError::AssetNotAccepted {} => {},
+        _ => panic!("Must return asset not accepted error"),
+    }
+}
+
+#[test]
+fn volume_statistics() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+    create_request(deps.as_mut(), mock_env(), "merchant", 1000, "uluna");
+    create_request(deps.as_mut(), mock_env(), "merchant", 500, "uluna");
+    create_request(deps.as_mut(), mock_env(), "other_merchant", 200, "uusd");
+    pay_request(deps.as_mut(), mock_env(), "customer", "1", 1000, "uluna");
+    pay_request(deps.as_mut(), mock_env(), "customer", "2", 500, "uluna");
+    pay_request(deps.as_mut(), mock_env(), "customer", "3", 100, "uusd");
+    execute(deps.as_mut(), mock_env(), mock_info("customer", &[]), ExecuteMsg::SettlePaymentRequest { id: String::from("1") }).unwrap();
+    execute(deps.as_mut(), mock_env(), mock_info("customer", &[]), ExecuteMsg::RaiseDispute { id: String::from("2"), reason: String::from("damaged") }).unwrap();
+    execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), ExecuteMsg::ResolveDispute { id: String::from("2"), merchant_assets: vec![uluna(200)] }).unwrap();
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::GetMerchantStats { merchant: String::from("merchant") }).unwrap();
+    let value: StatsResponse = from_binary(&res).unwrap();
+    assert_eq!(value.stats, Stats {
+        requests_created: 2,
+        requests_paid: 2,
+        requests_settled: 1,
+        requests_refunded: 1,
+        paid_volume: vec![uluna(1500)],
+        settled_volume: vec![uluna(1200)],
+        refunded_volume: vec![uluna(300)],
+    });
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::GetAssetStats { asset: AssetInfo::NativeToken { denom: String::from("uluna") } }).unwrap();
+    let value: AssetStatsResponse = from_binary(&res).unwrap();
+    assert_eq!(value.stats, AssetStats { paid: Uint128::from(1500u128), settled: Uint128::from(1200u128), refunded: Uint128::from(300u128) });
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::GetTotalStats {}).unwrap();
+    let value: StatsResponse = from_binary(&res).unwrap();
+    assert_eq!(value.stats.requests_created, 3);
+    assert_eq!(value.stats.requests_paid, 2);
+    assert_eq!(value.stats.paid_volume, vec![uluna(1500), asset("uusd", 100)]);
+}
+
+fn legacy_payment_request(id: &str, paid_amount: u128, customer: &str) -> LegacyPaymentRequest {
+    LegacyPaymentRequest {
+        id: String::from(id),
+        asset: uluna(1000),
+        order_id: String::from("order"),
+        paid_amount: Uint128::from(paid_amount),
+        refund_amount: Uint128::zero(),
+        merchant: Addr::unchecked("merchant"),
+        customer: Addr::unchecked(customer),
+    }
+}
+
+#[test]
+fn migrate_legacy_storage() {
+    let mut deps = mock_dependencies(&[]);
+    set_contract_version(&mut deps.storage, "crates.io:sps", "0.1.0").unwrap();
+    LEGACY_STATE.save(&mut deps.storage, &LegacyState { last_id: Uint64::from(3u64), shop: Addr::unchecked("shop") }).unwrap();
+    for p in [legacy_payment_request("1", 0, "0"), legacy_payment_request("2", 1000, "customer"), legacy_payment_request("3", 400, "customer")] {
+        LEGACY_PAYMENT_REQUESTS.save(&mut deps.storage, p.id.clone(), &p).unwrap();
+    }
+
+    migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+    let version = get_contract_version(&deps.storage).unwrap();
+    assert_eq!(version.contract, "crates.io:escrow");
+    assert_eq!(version.version, env!("CARGO_PKG_VERSION"));
+
+    let state = STATE.load(&deps.storage).unwrap();
+    assert_eq!(state.last_id, Uint64::from(3u64));
+    assert_eq!(state.admin, Addr::unchecked("shop"));
+
+    let pr = payment_requests().load(&deps.storage, request_key("1").unwrap()).unwrap();
+    assert_eq!(pr.status, PaymentRequestStatus::Unpaid);
+    assert_eq!(pr.assets, vec![uluna(1000)]);
+    assert_eq!(pr.paid_assets, vec![uluna(0)]);
+    let pr = payment_requests().load(&deps.storage, request_key("2").unwrap()).unwrap();
+    assert_eq!(pr.status, PaymentRequestStatus::Paid);
+    assert_eq!(pr.paid_at, None);
+    let pr = payment_requests().load(&deps.storage, request_key("3").unwrap()).unwrap();
+    assert_eq!(pr.status, PaymentRequestStatus::PartiallyPaid);
+    assert_eq!(pr.paid_at, None);
+
+    // the indexes cover the migrated requests
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::ListByMerchant { merchant: String::from("merchant"), start_after: None, limit: None }).unwrap();
+    let value: PaymentRequestsResponse = from_binary(&res).unwrap();
+    assert_eq!(value.payment_requests.len(), 3);
+
+    assert!(LEGACY_PAYMENT_REQUESTS.may_load(&deps.storage, String::from("2")).unwrap().is_none());
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info("customer", &[]), ExecuteMsg::SettlePaymentRequest { id: String::from("2") }).unwrap();
+    assert_eq!(res.messages[0].msg, bank_send("merchant", 1000, "uluna"));
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::ListContributions { id: String::from("3"), start_after: None, limit: None }).unwrap();
+    let value: ContributionsResponse = from_binary(&res).unwrap();
+    assert_eq!(value.contributions[0].assets, vec![uluna(400)]);
+}
+
+#[test]
+fn migrated_requests_are_not_released() {
+    let mut deps = mock_dependencies(&[]);
+    set_contract_version(&mut deps.storage, "crates.io:sps", "0.1.0").unwrap();
+    LEGACY_STATE.save(&mut deps.storage, &LegacyState { last_id: Uint64::from(1u64), shop: Addr::unchecked("shop") }).unwrap();
+    let p = legacy_payment_request("1", 1000, "customer");
+    LEGACY_PAYMENT_REQUESTS.save(&mut deps.storage, p.id.clone(), &p).unwrap();
+    migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("shop", &[]),
+        ExecuteMsg::UpdateConfig { admin: None, fee_bps: None, confirmation_window: Some(24 * 60 * 60) },
+    ).unwrap();
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(2 * 24 * 60 * 60);
+    let res = execute(
+        deps.as_mut(),
+        env,
+        mock_info("keeper", &[]),
+        ExecuteMsg::Release { id: String::from("1") },
+    ).unwrap_err();
+    match res {
+        ContractError::ReleaseDisabled {} => {},
+        _ => panic!("Must return release disabled error"),
+    }
+}
+
+#[test]
+fn migrate_rejects_unknown_versions() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+    migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+
+    for (contract, version) in [("crates.io:escrow", "9.0.0"), ("crates.io:cw20-base", "0.1.0")] {
+        set_contract_version(&mut deps.storage, contract, version).unwrap();
+        let res = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
+        match res {
+            ContractError::InvalidMigration { .. } => {},
+            _ => panic!("Must return invalid migration error"),
+        }
+    }
+}
+
+fn escrow_event(action: &str, id: &str, customer: &str, amount: &str) -> Event {
+    Event::new("escrow")
+        .add_attribute("action", action)
+        .add_attribute("id", id)
+        .add_attribute("merchant", "merchant")
+        .add_attribute("customer", customer)
+        .add_attribute("asset", "uluna")
+        .add_attribute("amount", amount)
+}
+
+#[test]
+fn lifecycle_events() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+    let create = ExecuteMsg::CreatePaymentRequest { assets: vec![uluna(1000)], order_id: String::from("1"), expires: None };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("merchant", &[]), create.clone()).unwrap();
+    assert_eq!(res.events, vec![escrow_event("create", "1", "0", "1000")]);
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info("customer", &coins(1000, "uluna")), ExecuteMsg::PayIntoPaymentRequest { id: String::from("1") }).unwrap();
+    assert_eq!(res.events, vec![escrow_event("pay", "1", "customer", "1000").add_attribute("payer", "customer")]);
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info("customer", &[]), ExecuteMsg::SettlePaymentRequest { id: String::from("1") }).unwrap();
+    assert_eq!(res.events, vec![escrow_event("settle", "1", "customer", "1000")]);
+
+    execute(deps.as_mut(), mock_env(), mock_info("merchant", &[]), create.clone()).unwrap();
+    let res = execute(deps.as_mut(), mock_env(), mock_info("merchant", &[]), ExecuteMsg::CancelPaymentRequest { id: String::from("2") }).unwrap();
+    assert_eq!(res.events, vec![escrow_event("cancel", "2", "0", "1000")]);
+
+    execute(deps.as_mut(), mock_env(), mock_info("merchant", &[]), create).unwrap();
+    pay_request(deps.as_mut(), mock_env(), "customer", "3", 1000, "uluna");
+    execute(deps.as_mut(), mock_env(), mock_info("customer", &[]), ExecuteMsg::RaiseDispute { id: String::from("3"), reason: String::from("never shipped") }).unwrap();
+    let res = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), ExecuteMsg::ResolveDispute { id: String::from("3"), merchant_assets: vec![] }).unwrap();
+    assert_eq!(res.events, vec![escrow_event("refund", "3", "customer", "1000")]);
+}
+
+fn create_intent(deps: DepsMut, env: Env) {
+    let expires = Expiration::AtHeight(env.block.height + 100);
+    execute(
+        deps,
+        env,
+        mock_info("customer", &coins(1000, "uluna")),
+        ExecuteMsg::CreatePaymentIntent { merchant: String::from("merchant"), description: String::from("custom bike frame"), expires },
+    ).unwrap();
+}
+
+#[test]
+fn accept_payment_intent() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+    create_intent(deps.as_mut(), mock_env());
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::ListIntentsByMerchant { merchant: String::from("merchant"), start_after: None, limit: None }).unwrap();
+    let value: PaymentIntentsResponse = from_binary(&res).unwrap();
+    assert_eq!(value.payment_intents.len(), 1);
+    assert_eq!(value.payment_intents[0].assets, vec![uluna(1000)]);
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info("other_merchant", &[]), ExecuteMsg::AcceptPaymentIntent { id: String::from("1") }).unwrap_err();
+    match res {
+        ContractError::Unauthorized {} => {},
+        _ => panic!("Must return unauthorized error"),
+    }
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info("merchant", &[]), ExecuteMsg::AcceptPaymentIntent { id: String::from("1") }).unwrap();
+    assert_eq!(res.messages.len(), 0);
+    let pr = payment_requests().load(&deps.storage, request_key("1").unwrap()).unwrap();
+    assert_eq!(pr.status, PaymentRequestStatus::Paid);
+    assert_eq!(pr.customer, "customer");
+    assert_eq!(pr.order_id, "custom bike frame");
+    assert!(query(deps.as_ref(), mock_env(), QueryMsg::GetPaymentIntent { id: String::from("1") }).is_err());
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info("customer", &[]), ExecuteMsg::SettlePaymentRequest { id: String::from("1") }).unwrap();
+    assert_eq!(res.messages[0].msg, bank_send("merchant", 1000, "uluna"));
+}
+
+#[test]
+fn reject_and_reclaim_payment_intent() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+    create_intent(deps.as_mut(), mock_env());
+    create_intent(deps.as_mut(), mock_env());
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("customer", &coins(1000, "uluna")),
+        ExecuteMsg::CreatePaymentIntent { merchant: String::from("merchant"), description: String::from("custom bike frame"), expires: Expiration::Never {} },
+    ).unwrap_err();
+    match res {
+        ContractError::InvalidExpiration {} => {},
+        _ => panic!("Must return invalid expiration error"),
+    }
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info("merchant", &[]), ExecuteMsg::RejectPaymentIntent { id: String::from("1") }).unwrap();
+    assert_eq!(res.messages[0].msg, bank_send("customer", 1000, "uluna"));
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info("anyone", &[]), ExecuteMsg::ReclaimPaymentIntent { id: String::from("2") }).unwrap_err();
+    match res {
+        ContractError::NotExpired {} => {},
+        _ => panic!("Must return not expired error"),
+    }
+
+    let mut env = mock_env();
+    env.block.height += 100;
+    let res = execute(deps.as_mut(), env.clone(), mock_info("merchant", &[]), ExecuteMsg::AcceptPaymentIntent { id: String::from("2") }).unwrap_err();
+    match res {
+        ContractError::Expired {} => {},
+        _ => panic!("Must return expired error"),
+    }
+    let res = execute(deps.as_mut(), env, mock_info("anyone", &[]), ExecuteMsg::ReclaimPaymentIntent { id: String::from("2") }).unwrap();
+    assert_eq!(res.messages[0].msg, bank_send("customer", 1000, "uluna"));
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::ListIntentsByCustomer { customer: String::from("customer"), start_after: None, limit: None }).unwrap();
+    let value: PaymentIntentsResponse = from_binary(&res).unwrap();
+    assert!(value.payment_intents.is_empty());
+}
+
+#[test]
+fn list_intents_in_creation_order() {
+    let mut deps = mock_dependencies(&[]);
+    init_contract(deps.as_mut(), mock_env());
+    for _ in 0..11 {
+        create_intent(deps.as_mut(), mock_env());
+    }
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::ListIntentsByMerchant { merchant: String::from("merchant"), start_after: None, limit: None }).unwrap();
+    let value: PaymentIntentsResponse = from_binary(&res).unwrap();
+    let ids: Vec<String> = value.payment_intents.iter().map(|i| i.id.clone()).collect();
+    assert_eq!(ids, (1..11).map(|i| i.to_string()).collect::<Vec<String>>());
+
+    let res = query(deps.as_ref(), mock_env(), QueryMsg::ListIntentsByCustomer { customer: String::from("customer"), start_after: Some(String::from("9")), limit: None }).unwrap();
+    let value: PaymentIntentsResponse = from_binary(&res).unwrap();
+    let ids: Vec<String> = value.payment_intents.iter().map(|i| i.id.clone()).collect();
+    assert_eq!(ids, vec!["10", "11"]);
+}
+
 // #[test]
 // fn opponent_move() {
 //     let mut deps = mock_dependencies(&coins(2, "token"));
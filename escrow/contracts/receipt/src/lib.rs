@@ -0,0 +1,46 @@
+use cosmwasm_std::Empty;
+use escrow::msg::ReceiptMetadata;
+
+pub use cw721_base::{ContractError, InstantiateMsg, MintMsg, MinterResponse, QueryMsg};
+
+/// cw721 collection of escrow receipts. Unlike stock cw721-base, the tokens keep the
+/// `ReceiptMetadata` the escrow mints them with.
+pub type ReceiptContract<'a> = cw721_base::Cw721Contract<'a, ReceiptMetadata, Empty>;
+
+pub type ExecuteMsg = cw721_base::ExecuteMsg<ReceiptMetadata>;
+
+#[cfg(not(feature = "library"))]
+pub mod entry {
+    use super::*;
+
+    use cosmwasm_std::entry_point;
+    use cosmwasm_std::{Binary, Deps, DepsMut, Env, MessageInfo, Response, StdResult};
+
+    #[entry_point]
+    pub fn instantiate(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        msg: InstantiateMsg,
+    ) -> StdResult<Response> {
+        ReceiptContract::default().instantiate(deps, env, info, msg)
+    }
+
+    #[entry_point]
+    pub fn execute(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        msg: ExecuteMsg,
+    ) -> Result<Response, ContractError> {
+        ReceiptContract::default().execute(deps, env, info, msg)
+    }
+
+    #[entry_point]
+    pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
+        ReceiptContract::default().query(deps, env, msg)
+    }
+}
+
+#[cfg(test)]
+mod tests;
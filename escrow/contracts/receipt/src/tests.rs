@@ -0,0 +1,145 @@
+use super::*;
+
+use asset::{Asset, AssetInfo};
+use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info, MOCK_CONTRACT_ADDR};
+use cosmwasm_std::{coins, from_binary, Addr, CosmosMsg, StdResult, Uint128, WasmMsg};
+use cw721::NftInfoResponse;
+use escrow::contract::{execute as escrow_execute, instantiate as escrow_instantiate};
+use escrow::msg::{ExecuteMsg as EscrowExecuteMsg, InstantiateMsg as EscrowInstantiateMsg};
+use escrow::state::STATE;
+
+/// Settles a payment request on an escrow whose receipt collection is `receipts` and
+/// returns the mint it sends there
+fn settle_with_receipt() -> ExecuteMsg {
+    let mut deps = mock_dependencies(&[]);
+    let msg = EscrowInstantiateMsg {
+        shop: Addr::unchecked("shop"),
+        arbiter: None,
+        fee_bps: 0,
+        confirmation_window: 0,
+        receipt_code_id: None,
+    };
+    escrow_instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+    STATE
+        .update(&mut deps.storage, |mut state| -> StdResult<_> {
+            state.receipt_contract = Some(Addr::unchecked("receipts"));
+            Ok(state)
+        })
+        .unwrap();
+
+    let steps = vec![
+        (
+            "merchant",
+            vec![],
+            EscrowExecuteMsg::ApplyAsMerchant {
+                name: String::from("merchant"),
+                payout_address: None,
+                accepted_assets: vec![],
+            },
+        ),
+        (
+            "shop",
+            vec![],
+            EscrowExecuteMsg::ApproveMerchant {
+                merchant: String::from("merchant"),
+            },
+        ),
+        (
+            "merchant",
+            vec![],
+            EscrowExecuteMsg::CreatePaymentRequest {
+                assets: vec![Asset {
+                    info: AssetInfo::NativeToken {
+                        denom: String::from("uluna"),
+                    },
+                    amount: Uint128::from(1000u128),
+                }],
+                order_id: String::from("order-1"),
+                expires: None,
+            },
+        ),
+        (
+            "customer",
+            coins(1000, "uluna"),
+            EscrowExecuteMsg::PayIntoPaymentRequest {
+                id: String::from("1"),
+            },
+        ),
+    ];
+    for (sender, funds, msg) in steps {
+        escrow_execute(deps.as_mut(), mock_env(), mock_info(sender, &funds), msg).unwrap();
+    }
+    let res = escrow_execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("customer", &[]),
+        EscrowExecuteMsg::SettlePaymentRequest {
+            id: String::from("1"),
+        },
+    )
+    .unwrap();
+    res.messages
+        .into_iter()
+        .find_map(|m| match m.msg {
+            CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, msg, .. })
+                if contract_addr == "receipts" =>
+            {
+                Some(from_binary(&msg).unwrap())
+            }
+            _ => None,
+        })
+        .unwrap()
+}
+
+#[test]
+fn stores_receipt_metadata() {
+    let mut deps = mock_dependencies(&[]);
+    let contract = ReceiptContract::default();
+    contract
+        .instantiate(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("creator", &[]),
+            InstantiateMsg {
+                name: String::from("escrow receipts"),
+                symbol: String::from("RCPT"),
+                minter: String::from(MOCK_CONTRACT_ADDR),
+            },
+        )
+        .unwrap();
+
+    contract
+        .execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info(MOCK_CONTRACT_ADDR, &[]),
+            settle_with_receipt(),
+        )
+        .unwrap();
+
+    let res = contract
+        .query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::NftInfo {
+                token_id: String::from("1"),
+            },
+        )
+        .unwrap();
+    let info: NftInfoResponse<ReceiptMetadata> = from_binary(&res).unwrap();
+    assert_eq!(
+        info.extension,
+        ReceiptMetadata {
+            payment_request_id: String::from("1"),
+            merchant: Addr::unchecked("merchant"),
+            order_id: String::from("order-1"),
+            assets: vec![Asset {
+                info: AssetInfo::NativeToken {
+                    denom: String::from("uluna"),
+                },
+                amount: Uint128::from(1000u128),
+            }],
+            settled_at: mock_env().block.time,
+        }
+    );
+}
@@ -3,9 +3,17 @@ use serde::{Deserialize, Serialize};
 use std::fmt;
 
 use cosmwasm_std::{
-    to_binary, Addr, Api, BankMsg, CanonicalAddr, Coin, CosmosMsg, Decimal, MessageInfo,
-    QuerierWrapper, StdError, StdResult, SubMsg, Uint128, WasmMsg,
+    to_binary, Addr, BankMsg, Binary, Coin, CosmosMsg, StdError, StdResult, SubMsg, Uint128, WasmMsg,
 };
+use cw20::Cw20ExecuteMsg;
+
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+pub mod tax;
+#[cfg(test)]
+mod tests;
+
+pub use crate::tax::{CappedRateTax, TaxCalculator, TaxQuerier, TerraTaxQuerier, ZeroTax};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Asset {
@@ -19,69 +27,98 @@ impl fmt::Display for Asset {
     }
 }
 
-static DECIMAL_FRACTION: Uint128 = Uint128::new(1_000_000_000_000_000_000u128);
-
 impl Asset {
     pub fn is_native_token(&self) -> bool {
         self.info.is_native_token()
     }
 
-    // pub fn compute_tax(&self, querier: &QuerierWrapper) -> StdResult<Uint128> {
-    //     let amount = self.amount;
-    //     if let AssetInfo::NativeToken { denom } = &self.info {
-    //         if denom == "uluna" {
-    //             Ok(Uint128::zero())
-    //         } else {
-    //             let terra_querier = TerraQuerier::new(querier);
-    //             let tax_rate: Decimal = (terra_querier.query_tax_rate()?).rate;
-    //             let tax_cap: Uint128 = (terra_querier.query_tax_cap(denom.to_string())?).cap;
-    //             Ok(std::cmp::min(
-    //                 amount.checked_sub(amount.multiply_ratio(
-    //                     DECIMAL_FRACTION,
-    //                     DECIMAL_FRACTION * tax_rate + DECIMAL_FRACTION,
-    //                 ))?,
-    //                 tax_cap,
-    //             ))
-    //         }
-    //     } else {
-    //         Ok(Uint128::zero())
-    //     }
-    // }
+    pub fn compute_tax(&self, tax: &dyn TaxCalculator) -> StdResult<Uint128> {
+        match &self.info {
+            AssetInfo::NativeToken { denom } => tax.compute_tax(denom, self.amount),
+            AssetInfo::Token { .. } => Ok(Uint128::zero()),
+        }
+    }
 
-    // pub fn deduct_tax(&self, querier: &QuerierWrapper) -> StdResult<Coin> {
-    //     let amount = self.amount;
-    //     if let AssetInfo::NativeToken { denom } = &self.info {
-    //         Ok(Coin {
-    //             denom: denom.to_string(),
-    //             amount: amount.checked_sub(self.compute_tax(querier)?)?,
-    //         })
-    //     } else {
-    //         Err(StdError::generic_err("cannot deduct tax from token asset"))
-    //     }
-    // }
+    pub fn deduct_tax(&self, tax: &dyn TaxCalculator) -> StdResult<Coin> {
+        let amount = self.amount;
+        if let AssetInfo::NativeToken { denom } = &self.info {
+            Ok(Coin {
+                denom: denom.to_string(),
+                amount: amount.checked_sub(self.compute_tax(tax)?)?,
+            })
+        } else {
+            Err(StdError::generic_err("cannot deduct tax from token asset"))
+        }
+    }
 
-    // pub fn into_msg(self, querier: &QuerierWrapper, recipient: Addr) -> StdResult<CosmosMsg> {
-    //     let amount = self.amount;
-
-    //     match &self.info {
-    //         AssetInfo::Token { contract_addr } => Ok(CosmosMsg::Wasm(WasmMsg::Execute {
-    //             contract_addr: contract_addr.to_string(),
-    //             msg: to_binary(&Cw20ExecuteMsg::Transfer {
-    //                 recipient: recipient.to_string(),
-    //                 amount,
-    //             })?,
-    //             funds: vec![],
-    //         })),
-    //         AssetInfo::NativeToken { .. } => Ok(CosmosMsg::Bank(BankMsg::Send {
-    //             to_address: recipient.to_string(),
-    //             amount: vec![self.deduct_tax(querier)?],
-    //         })),
-    //     }
-    // }
+    /// Transfers the asset to `recipient`, a bank send or a cw20 `Transfer`
+    pub fn into_msg(self, recipient: Addr) -> StdResult<CosmosMsg> {
+        self.into_msg_with_tax(recipient, &ZeroTax)
+    }
 
-    // pub fn into_submsg(self, querier: &QuerierWrapper, recipient: Addr) -> StdResult<SubMsg> {
-    //     Ok(SubMsg::new(self.into_msg(querier, recipient)?))
-    // }
+    /// `into_msg`, with native transfers net of the tax computed by `tax`
+    pub fn into_msg_with_tax(
+        self,
+        recipient: Addr,
+        tax: &dyn TaxCalculator,
+    ) -> StdResult<CosmosMsg> {
+        let amount = self.amount;
+
+        match &self.info {
+            AssetInfo::Token { contract_addr } => Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: contract_addr.to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: recipient.to_string(),
+                    amount,
+                })?,
+                funds: vec![],
+            })),
+            AssetInfo::NativeToken { .. } => Ok(CosmosMsg::Bank(BankMsg::Send {
+                to_address: recipient.to_string(),
+                amount: vec![self.deduct_tax(tax)?],
+            })),
+        }
+    }
+
+    pub fn into_submsg(self, recipient: Addr) -> StdResult<SubMsg> {
+        Ok(SubMsg::new(self.into_msg(recipient)?))
+    }
+
+    /// Sends the asset to `contract` and has it execute `msg`: a cw20 `Send` whose hook
+    /// receives `msg`, or `msg` executed with the coins attached for native tokens
+    pub fn into_send_msg(self, contract: Addr, msg: Binary) -> StdResult<CosmosMsg> {
+        self.into_send_msg_with_tax(contract, msg, &ZeroTax)
+    }
+
+    pub fn into_send_msg_with_tax(
+        self,
+        contract: Addr,
+        msg: Binary,
+        tax: &dyn TaxCalculator,
+    ) -> StdResult<CosmosMsg> {
+        let amount = self.amount;
+
+        match &self.info {
+            AssetInfo::Token { contract_addr } => Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: contract_addr.to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Send {
+                    contract: contract.to_string(),
+                    amount,
+                    msg,
+                })?,
+                funds: vec![],
+            })),
+            AssetInfo::NativeToken { .. } => Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: contract.to_string(),
+                msg,
+                funds: vec![self.deduct_tax(tax)?],
+            })),
+        }
+    }
+
+    pub fn into_send_submsg(self, contract: Addr, msg: Binary) -> StdResult<SubMsg> {
+        Ok(SubMsg::new(self.into_send_msg(contract, msg)?))
+    }
 
     // pub fn assert_sent_native_token_balance(&self, message_info: &MessageInfo) -> StdResult<()> {
     //     if let AssetInfo::NativeToken { denom } = &self.info {
@@ -169,4 +206,4 @@ impl AssetInfo {
             }
         }
     }
-}
\ No newline at end of file
+}
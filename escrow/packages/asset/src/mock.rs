@@ -0,0 +1,44 @@
+use cosmwasm_std::{Decimal, StdError, StdResult, Uint128};
+use std::collections::HashMap;
+
+use crate::tax::TaxQuerier;
+
+/// Fixed tax rate and caps for tests
+#[derive(Default)]
+pub struct MockTaxQuerier {
+    rate: Decimal,
+    caps: HashMap<String, Uint128>,
+}
+
+impl MockTaxQuerier {
+    pub fn new(rate: Decimal, caps: &[(&str, Uint128)]) -> Self {
+        MockTaxQuerier {
+            rate,
+            caps: caps
+                .iter()
+                .map(|(denom, cap)| (denom.to_string(), *cap))
+                .collect(),
+        }
+    }
+
+    pub fn set_tax_rate(&mut self, rate: Decimal) {
+        self.rate = rate;
+    }
+
+    pub fn set_tax_cap(&mut self, denom: &str, cap: Uint128) {
+        self.caps.insert(denom.to_string(), cap);
+    }
+}
+
+impl TaxQuerier for MockTaxQuerier {
+    fn tax_rate(&self) -> StdResult<Decimal> {
+        Ok(self.rate)
+    }
+
+    fn tax_cap(&self, denom: &str) -> StdResult<Uint128> {
+        self.caps
+            .get(denom)
+            .copied()
+            .ok_or_else(|| StdError::generic_err(format!("no tax cap for {}", denom)))
+    }
+}
@@ -0,0 +1,121 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{CustomQuery, Decimal, QuerierWrapper, QueryRequest, StdResult, Uint128};
+
+static DECIMAL_FRACTION: Uint128 = Uint128::new(1_000_000_000_000_000_000u128);
+
+/// Tax levied by the chain on a native transfer, deducted from the amount sent
+pub trait TaxCalculator {
+    fn compute_tax(&self, denom: &str, amount: Uint128) -> StdResult<Uint128>;
+}
+
+/// For chains without transfer taxes
+pub struct ZeroTax;
+
+impl TaxCalculator for ZeroTax {
+    fn compute_tax(&self, _denom: &str, _amount: Uint128) -> StdResult<Uint128> {
+        Ok(Uint128::zero())
+    }
+}
+
+/// Source of the tax rate and the per-denom tax cap
+pub trait TaxQuerier {
+    fn tax_rate(&self) -> StdResult<Decimal>;
+    fn tax_cap(&self, denom: &str) -> StdResult<Uint128>;
+}
+
+/// Taxes `rate` of the amount received, capped at the denom's cap. Denoms in `exempt`
+/// (e.g. uluna on Terra) are not taxed.
+pub struct CappedRateTax<Q: TaxQuerier> {
+    pub querier: Q,
+    pub exempt: Vec<String>,
+}
+
+impl<Q: TaxQuerier> CappedRateTax<Q> {
+    pub fn new(querier: Q, exempt: Vec<String>) -> Self {
+        CappedRateTax { querier, exempt }
+    }
+}
+
+impl<Q: TaxQuerier> TaxCalculator for CappedRateTax<Q> {
+    fn compute_tax(&self, denom: &str, amount: Uint128) -> StdResult<Uint128> {
+        if self.exempt.iter().any(|d| d == denom) {
+            return Ok(Uint128::zero());
+        }
+        let tax_rate = self.querier.tax_rate()?;
+        let tax_cap = self.querier.tax_cap(denom)?;
+        Ok(std::cmp::min(
+            amount.checked_sub(amount.multiply_ratio(
+                DECIMAL_FRACTION,
+                DECIMAL_FRACTION * tax_rate + DECIMAL_FRACTION,
+            ))?,
+            tax_cap,
+        ))
+    }
+}
+
+/// Reads the tax rate and caps from Terra's treasury module
+pub struct TerraTaxQuerier<'a> {
+    pub querier: &'a QuerierWrapper<'a>,
+}
+
+impl<'a> TerraTaxQuerier<'a> {
+    pub fn new(querier: &'a QuerierWrapper<'a>) -> Self {
+        TerraTaxQuerier { querier }
+    }
+
+    /// `CappedRateTax` with uluna exempt, as on Terra
+    pub fn calculator(querier: &'a QuerierWrapper<'a>) -> CappedRateTax<Self> {
+        CappedRateTax::new(Self::new(querier), vec!["uluna".to_string()])
+    }
+}
+
+impl<'a> TaxQuerier for TerraTaxQuerier<'a> {
+    fn tax_rate(&self) -> StdResult<Decimal> {
+        let res: TaxRateResponse = self.querier.custom_query(&QueryRequest::Custom(
+            TreasuryQueryWrapper {
+                route: "treasury".to_string(),
+                query_data: TreasuryQuery::TaxRate {},
+            },
+        ))?;
+        Ok(res.rate)
+    }
+
+    fn tax_cap(&self, denom: &str) -> StdResult<Uint128> {
+        let res: TaxCapResponse = self.querier.custom_query(&QueryRequest::Custom(
+            TreasuryQueryWrapper {
+                route: "treasury".to_string(),
+                query_data: TreasuryQuery::TaxCap {
+                    denom: denom.to_string(),
+                },
+            },
+        ))?;
+        Ok(res.cap)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TreasuryQueryWrapper {
+    pub route: String,
+    pub query_data: TreasuryQuery,
+}
+
+impl CustomQuery for TreasuryQueryWrapper {}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TreasuryQuery {
+    TaxRate {},
+    TaxCap { denom: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TaxRateResponse {
+    pub rate: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TaxCapResponse {
+    pub cap: Uint128,
+}
@@ -18,13 +18,33 @@ async function initTest() {
     // Init-ing contract
     process.stdout.write("Instantiating contract...")
     const initContract = await instantiateContract(terra, deployer, deployer, contractCodeId, {
-        shop: 'shop_contract_address'
+        shop: deployer.key.accAddress,
+        fee_bps: 0,
+        confirmation_window: 259200
     });
     console.log(`Done!`);
     contractAddress = initContract.logs[0].events[0].attributes[0].value;
     console.log(contractAddress)
 }
 
+async function registerMerchant(merchant: Wallet) {
+    await sendTransaction(terra, merchant, [
+        new MsgExecuteContract(merchant.key.accAddress, contractAddress, {
+            apply_as_merchant: {
+                name: merchant.key.accAddress,
+                accepted_assets: [],
+            },
+        }),
+    ], false);
+    await sendTransaction(terra, deployer, [
+        new MsgExecuteContract(deployer.key.accAddress, contractAddress, {
+            approve_merchant: {
+                merchant: merchant.key.accAddress,
+            },
+        }),
+    ], false);
+}
+
 async function deployCw20AndMint(to: Wallet) {
     const cw20CodeId = await storeCode(
         terra,
@@ -55,12 +75,12 @@ async function testCreatePaymentRequest() {
     const result = await sendTransaction(terra, user1, [
         new MsgExecuteContract(user1.key.accAddress, contractAddress, {
             create_payment_request: {
-                asset: {
+                assets: [{
                     info :{
                         native_token: { denom: 'uusd'},
                     },
                     amount: "1000000",
-                },
+                }],
                 order_id: "123",
             },
           }),
@@ -82,12 +102,12 @@ async function testPayPaymentRequestWithCw20() {
     const result = await sendTransaction(terra, user1, [
         new MsgExecuteContract(user1.key.accAddress, contractAddress, {
             create_payment_request: {
-                asset: {
+                assets: [{
                     info :{
                         token: { contract_addr: mirrorToken},
                     },
                     amount: "10000000",
-                },
+                }],
                 order_id: "123",
             },
           }),
@@ -115,19 +135,19 @@ async function testPayPaymentRequestWithCw20() {
             id: prId
         }
     })
-    expect(pr.payment_request.paid_amount).to.eq("10000000");
+    expect(pr.payment_request.paid_assets[0].amount).to.eq("10000000");
 }
 
 async function testSettlementWithCw20() {
     const result = await sendTransaction(terra, deployer, [
         new MsgExecuteContract(deployer.key.accAddress, contractAddress, {
             create_payment_request: {
-                asset: {
+                assets: [{
                     info :{
                         token: { contract_addr: mirrorToken},
                     },
                     amount: "10000000",
-                },
+                }],
                 order_id: "123",
             },
           }),
@@ -162,12 +182,12 @@ async function testSettlePaymentRequest() {
     const result = await sendTransaction(terra, user1, [
         new MsgExecuteContract(user1.key.accAddress, contractAddress, {
             create_payment_request: {
-                asset: {
+                assets: [{
                     info :{
                         native_token: { denom: 'uusd'},
                     },
                     amount: "10000000",
-                },
+                }],
                 order_id: "123",
             },
           }),
@@ -200,12 +220,12 @@ async function testPayPaymentRequestWithLessAmount() {
     const result = await sendTransaction(terra, user1, [
         new MsgExecuteContract(user1.key.accAddress, contractAddress, {
             create_payment_request: {
-                asset: {
+                assets: [{
                     info :{
                         native_token: { denom: 'uluna'},
                     },
                     amount: "1000000",
-                },
+                }],
                 order_id: "123",
             },
           }),
@@ -228,6 +248,8 @@ async function testPayPaymentRequestWithLessAmount() {
 (async () => {
     await deployCw20AndMint(user1);
     await initTest();
+    await registerMerchant(deployer);
+    await registerMerchant(user1);
     await testCreatePaymentRequest();
     await testPayPaymentRequest();
     await testPayPaymentRequestWithLessAmount();
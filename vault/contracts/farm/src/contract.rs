@@ -467,7 +467,7 @@ pub fn close_position(
         &deps.querier,
         pair_info.contract_addr.clone(),
         &astroport_asset::Asset {
-            info: astro_other_asset,
+            info: astro_other_asset.clone(),
             amount: other_asset_amount,
         },
     )?
@@ -486,25 +486,28 @@ pub fn close_position(
     let mut msgs: Vec<CosmosMsg> = vec![];
 
     // Close LP and get base token + other token (message)
-    msgs.push(CosmosMsg::Wasm(WasmMsg::Execute {
-        contract_addr: farm.claim_asset_addr.to_string(),
-        funds: vec![],
-        msg: to_binary(&Cw20ExecuteMsg::Send {
+    msgs.push(
+        Asset {
+            info: AssetInfo::Token {
+                contract_addr: farm.claim_asset_addr.to_string(),
+            },
             amount: position_lp_balance,
-            contract: pair_info.contract_addr.to_string(),
-            msg: to_binary(&AstroportHookMsg::WithdrawLiquidity {})?,
-        })?,
-    }));
+        }
+        .into_send_msg(
+            pair_info.contract_addr.clone(),
+            to_binary(&AstroportHookMsg::WithdrawLiquidity {})?,
+        )?,
+    );
 
     // Swap to get repay amount
-    match astro_other_asset {
+    match &astro_other_asset {
         astroport_asset::AssetInfo::NativeToken { denom } => {
             msgs.push(CosmosMsg::Wasm(WasmMsg::Execute {
                 contract_addr: pair_info.contract_addr.to_string(),
                 funds: coins(other_asset_amount.into(), denom),
                 msg: to_binary(&AstroportExecuteMsg::Swap {
                     offer_asset: astroport_asset::Asset {
-                        info: astro_other_asset,
+                        info: astro_other_asset.clone(),
                         amount: other_asset_amount,
                     },
                     belief_price: None,
@@ -535,27 +538,30 @@ pub fn close_position(
             return Err(ContractError::WrongToken {});
         }
         astroport_asset::AssetInfo::Token { contract_addr } => {
-            // Repay debt (message)
-            msgs.push(CosmosMsg::Wasm(WasmMsg::Execute {
+            let base_asset_info = AssetInfo::Token {
                 contract_addr: contract_addr.to_string(),
-                funds: vec![],
-                msg: to_binary(&Cw20ExecuteMsg::Send {
+            };
+            // Repay debt (message)
+            msgs.push(
+                Asset {
+                    info: base_asset_info.clone(),
                     amount: repay_amount,
-                    contract: farm.vault_addr.to_string(),
-                    msg: to_binary(&VaultCw20HookMsg::Repay {
+                }
+                .into_send_msg(
+                    farm.vault_addr.clone(),
+                    to_binary(&VaultCw20HookMsg::Repay {
                         position_id: position.vault_position_id,
                     })?,
-                })?,
-            }));
+                )?,
+            );
             //  Send the rest back to sender (message)
-            msgs.push(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: contract_addr.to_string(),
-                funds: vec![],
-                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+            msgs.push(
+                Asset {
+                    info: base_asset_info,
                     amount: total_base_amount - repay_amount,
-                    recipient: position.owner.to_string(),
-                })?,
-            }))
+                }
+                .into_msg(position.owner.clone())?,
+            )
         }
     }
 
@@ -22,7 +22,7 @@ pub enum ContractError {
     #[error("Math error")]
     MathError {},
 
-    #[error("Query error {}")]
+    #[error("Query error {kind}")]
     QueryError { kind: String },
 }
 
@@ -220,15 +220,12 @@ fn withdraw(
                 msg: "not supported".into(),
             }));
         }
-        AssetInfo::Token { contract_addr } => {
-            let transfer_token_msg: CosmosMsg = CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: contract_addr,
-                msg: to_binary(&Cw20ExecuteMsg::Transfer {
-                    amount: tokens_to_withdraw,
-                    recipient: sender,
-                })?,
-                funds: vec![],
-            });
+        info @ AssetInfo::Token { .. } => {
+            let transfer_token_msg: CosmosMsg = Asset {
+                info,
+                amount: tokens_to_withdraw,
+            }
+            .into_msg(deps.api.addr_validate(&sender)?)?;
             return Ok(Response::new()
                 .add_messages(vec![burn_vault_token_msg, transfer_token_msg])
                 .add_attributes(vec![
@@ -383,20 +380,15 @@ pub fn borrow(
                 msg: "not implemented".into(),
             }))
         }
-        AssetInfo::Token {
-            contract_addr: token_contract_addr,
-        } => Ok(Response::new()
-            .add_message(CosmosMsg::Wasm(
-                // Deposit funds into another contract
-                WasmMsg::Execute {
-                    contract_addr: token_contract_addr.to_string(),
-                    msg: to_binary(&Cw20ExecuteMsg::Transfer {
-                        amount: borrow_amount,
-                        recipient: farm_addr.to_string(),
-                    })?,
-                    funds: vec![],
-                },
-            ))
+        info @ AssetInfo::Token { .. } => Ok(Response::new()
+            // Deposit funds into another contract
+            .add_message(
+                Asset {
+                    info,
+                    amount: borrow_amount,
+                }
+                .into_msg(farm_addr.clone())?,
+            )
             .add_attribute("position_id", position.id.clone())),
     }
 }
@@ -458,14 +450,15 @@ fn repay(
 
     let mut msgs: Vec<CosmosMsg> = vec![];
     if refund > Uint128::zero() {
-        msgs.push(CosmosMsg::Wasm(WasmMsg::Execute {
-            contract_addr: token_addr.into(),
-            funds: vec![],
-            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+        msgs.push(
+            Asset {
+                info: AssetInfo::Token {
+                    contract_addr: token_addr.to_string(),
+                },
                 amount: refund,
-                recipient: owner,
-            })?,
-        }))
+            }
+            .into_msg(deps.api.addr_validate(&owner)?)?,
+        )
     }
 
     let final_position_debt_share: Uint128 = debt_share_from_value(
@@ -0,0 +1,165 @@
+use crate::mock::MockTaxQuerier;
+use crate::{Asset, AssetInfo, CappedRateTax, TaxCalculator, ZeroTax};
+
+use cosmwasm_std::{coin, to_binary, Addr, BankMsg, CosmosMsg, Decimal, SubMsg, Uint128, WasmMsg};
+use cw20::Cw20ExecuteMsg;
+
+fn terra_tax() -> CappedRateTax<MockTaxQuerier> {
+    CappedRateTax::new(
+        MockTaxQuerier::new(
+            Decimal::percent(1),
+            &[("uusd", Uint128::new(1_000_000)), ("ukrw", Uint128::new(10))],
+        ),
+        vec!["uluna".to_string()],
+    )
+}
+
+fn native(denom: &str, amount: u128) -> Asset {
+    Asset {
+        info: AssetInfo::NativeToken {
+            denom: denom.to_string(),
+        },
+        amount: Uint128::new(amount),
+    }
+}
+
+fn token(amount: u128) -> Asset {
+    Asset {
+        info: AssetInfo::Token {
+            contract_addr: "token".to_string(),
+        },
+        amount: Uint128::new(amount),
+    }
+}
+
+#[test]
+fn into_msg() {
+    let recipient = Addr::unchecked("recipient");
+    assert_eq!(
+        native("uusd", 1000).into_msg(recipient.clone()).unwrap(),
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: "recipient".to_string(),
+            amount: vec![coin(1000, "uusd")],
+        })
+    );
+    let transfer = CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: "token".to_string(),
+        msg: to_binary(&Cw20ExecuteMsg::Transfer {
+            recipient: "recipient".to_string(),
+            amount: Uint128::new(1000),
+        })
+        .unwrap(),
+        funds: vec![],
+    });
+    assert_eq!(token(1000).into_msg(recipient.clone()).unwrap(), transfer);
+    assert_eq!(
+        token(1000).into_submsg(recipient).unwrap(),
+        SubMsg::new(transfer)
+    );
+}
+
+#[test]
+fn into_send_msg() {
+    let hook = to_binary(&"deposit").unwrap();
+    assert_eq!(
+        token(1000)
+            .into_send_msg(Addr::unchecked("vault"), hook.clone())
+            .unwrap(),
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "token".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Send {
+                contract: "vault".to_string(),
+                amount: Uint128::new(1000),
+                msg: hook.clone(),
+            })
+            .unwrap(),
+            funds: vec![],
+        })
+    );
+    let execute = CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: "vault".to_string(),
+        msg: hook.clone(),
+        funds: vec![coin(1000, "uusd")],
+    });
+    assert_eq!(
+        native("uusd", 1000)
+            .into_send_msg(Addr::unchecked("vault"), hook.clone())
+            .unwrap(),
+        execute
+    );
+    assert_eq!(
+        native("uusd", 1000)
+            .into_send_submsg(Addr::unchecked("vault"), hook)
+            .unwrap(),
+        SubMsg::new(execute)
+    );
+}
+
+#[test]
+fn capped_rate_tax() {
+    let tax = terra_tax();
+    // 1% of what the recipient receives: 1010 = 1000 + 10
+    assert_eq!(tax.compute_tax("uusd", Uint128::new(1010)).unwrap(), Uint128::new(10));
+    // capped
+    assert_eq!(tax.compute_tax("ukrw", Uint128::new(10_100)).unwrap(), Uint128::new(10));
+    // exempt
+    assert_eq!(tax.compute_tax("uluna", Uint128::new(1010)).unwrap(), Uint128::zero());
+    // unknown cap
+    assert!(tax.compute_tax("ueur", Uint128::new(1010)).is_err());
+
+    assert_eq!(ZeroTax.compute_tax("uusd", Uint128::new(1010)).unwrap(), Uint128::zero());
+}
+
+#[test]
+fn into_msg_with_tax() {
+    let recipient = Addr::unchecked("recipient");
+
+    let msg = native("uusd", 1010).into_msg(recipient.clone()).unwrap();
+    assert_eq!(
+        msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: "recipient".to_string(),
+            amount: vec![coin(1010, "uusd")],
+        })
+    );
+
+    let msg = native("uusd", 1010)
+        .into_msg_with_tax(recipient.clone(), &terra_tax())
+        .unwrap();
+    assert_eq!(
+        msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: "recipient".to_string(),
+            amount: vec![coin(1000, "uusd")],
+        })
+    );
+
+    let msg = native("uusd", 1010)
+        .into_send_msg_with_tax(Addr::unchecked("pair"), to_binary(&"swap").unwrap(), &terra_tax())
+        .unwrap();
+    assert_eq!(
+        msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "pair".to_string(),
+            msg: to_binary(&"swap").unwrap(),
+            funds: vec![coin(1000, "uusd")],
+        })
+    );
+
+    // cw20 transfers are never taxed
+    let token = token(1010);
+    assert_eq!(token.compute_tax(&terra_tax()).unwrap(), Uint128::zero());
+    let msg = token.into_msg_with_tax(recipient, &terra_tax()).unwrap();
+    assert_eq!(
+        msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "token".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: "recipient".to_string(),
+                amount: Uint128::new(1010),
+            })
+            .unwrap(),
+            funds: vec![],
+        })
+    );
+}